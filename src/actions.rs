@@ -0,0 +1,539 @@
+use std::{collections::HashSet, fs, path::Path, str::FromStr};
+
+use bevy::{
+    input::InputSystem,
+    prelude::*,
+    reflect::{DynamicEnum, DynamicVariant, FromReflect},
+};
+use bevy_egui::{egui, EguiContext};
+
+// Read at startup and written from the bindings window, in the working directory
+const BINDINGS_PATH: &str = "bindings.cfg";
+
+pub struct ActionPlugin;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect, FromReflect)]
+pub enum Action {
+    Spawn,
+    BrushShape,
+    BrushVelocity,
+    BrushProfile,
+    BrushSlower,
+    BrushFaster,
+    BrushTeam,
+    Leader,
+    FollowLeader,
+    Formation,
+    FormationShape,
+    Erase,
+    PlaceSink,
+    PlaceEmitter,
+    EmitterPattern,
+    Lifecycle,
+    PlaceFood,
+    Population,
+    TeamRules,
+    Attract,
+    PlaceTarget,
+    RemoveTarget,
+    // Held while placing a target to make it repel
+    Repel,
+    // Held while scrolling over a target to change its strength
+    Strength,
+    TargetMode,
+    PreviousTargetMode,
+    TargetStyle,
+    Pause,
+    Step,
+    Slower,
+    Faster,
+    RewindBack,
+    RewindForward,
+    RewindFast,
+    Record,
+    RecordFormat,
+    RecordRate,
+    RecordFilter,
+    // Tags the vehicle under the cursor for recording
+    RecordTag,
+    Replay,
+    ReplayPlay,
+    ReplayBack,
+    ReplayForward,
+    ReplaySlower,
+    ReplayFaster,
+    SaveSnapshot,
+    LoadSnapshot,
+    ColorMode,
+    RenderPath,
+    TrailMode,
+    TrailSelect,
+    HeatmapToggle,
+    HeatmapLayer,
+    HeatmapFasterDecay,
+    HeatmapSlowerDecay,
+    HeatmapClear,
+    HeatmapExport,
+    Bindings,
+}
+
+// Written as `Key(Space)`, `Mouse(Left)` or `Gamepad(South)` in the config
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButtonType),
+}
+
+impl Binding {
+    fn parse(text: &str) -> Option<Self> {
+        let (kind, name) = text.trim().strip_suffix(')')?.split_once('(')?;
+
+        match kind.trim() {
+            "Key" => parse_variant(name).map(Binding::Key),
+            "Mouse" => parse_other(name)
+                .map(MouseButton::Other)
+                .or_else(|| parse_variant(name))
+                .map(Binding::Mouse),
+            "Gamepad" => parse_other(name)
+                .map(GamepadButtonType::Other)
+                .or_else(|| parse_variant(name))
+                .map(Binding::Gamepad),
+            _ => None,
+        }
+    }
+}
+
+// Unnamed buttons are written as `Other(4)`, the closing parenthesis is already gone here
+fn parse_other<T: FromStr>(name: &str) -> Option<T> {
+    name.trim().strip_prefix("Other(")?.trim().parse().ok()
+}
+
+// Builds a field-less enum variant from its name
+fn parse_variant<T: FromReflect>(name: &str) -> Option<T> {
+    T::from_reflect(&DynamicEnum::new(
+        std::any::type_name::<T>(),
+        name.trim(),
+        DynamicVariant::Unit,
+    ))
+}
+
+// Inputs bound to each action, in the order they are listed
+#[derive(Resource, Clone)]
+pub struct ActionMap(Vec<(Action, Vec<Binding>)>);
+
+impl Default for ActionMap {
+    fn default() -> Self {
+        use Binding::{Gamepad, Key, Mouse};
+
+        Self(vec![
+            (
+                Action::Spawn,
+                vec![Key(KeyCode::Space), Gamepad(GamepadButtonType::South)],
+            ),
+            (Action::BrushShape, vec![Key(KeyCode::Key1)]),
+            (Action::BrushVelocity, vec![Key(KeyCode::Key2)]),
+            (Action::BrushProfile, vec![Key(KeyCode::Key3)]),
+            (Action::BrushSlower, vec![Key(KeyCode::Key4)]),
+            (Action::BrushFaster, vec![Key(KeyCode::Key5)]),
+            (Action::BrushTeam, vec![Key(KeyCode::Key6)]),
+            (Action::Leader, vec![Key(KeyCode::O)]),
+            (Action::FollowLeader, vec![Key(KeyCode::Key7)]),
+            (Action::Formation, vec![Key(KeyCode::R)]),
+            (Action::FormationShape, vec![Key(KeyCode::Key8)]),
+            (Action::Erase, vec![Key(KeyCode::X)]),
+            (Action::PlaceSink, vec![Key(KeyCode::Z)]),
+            (Action::PlaceEmitter, vec![Key(KeyCode::E)]),
+            (Action::EmitterPattern, vec![Key(KeyCode::Q)]),
+            (Action::Lifecycle, vec![Key(KeyCode::N)]),
+            (Action::PlaceFood, vec![Key(KeyCode::F)]),
+            (Action::Population, vec![Key(KeyCode::I)]),
+            (Action::TeamRules, vec![Key(KeyCode::U)]),
+            (
+                Action::Attract,
+                vec![
+                    Mouse(MouseButton::Left),
+                    Gamepad(GamepadButtonType::RightTrigger2),
+                ],
+            ),
+            (Action::PlaceTarget, vec![Mouse(MouseButton::Right)]),
+            (Action::RemoveTarget, vec![Mouse(MouseButton::Middle)]),
+            (Action::Repel, vec![Key(KeyCode::LShift)]),
+            (Action::Strength, vec![Key(KeyCode::LControl)]),
+            (
+                Action::TargetMode,
+                vec![Key(KeyCode::M), Gamepad(GamepadButtonType::RightTrigger)],
+            ),
+            (
+                Action::PreviousTargetMode,
+                vec![Gamepad(GamepadButtonType::LeftTrigger)],
+            ),
+            (Action::TargetStyle, vec![Key(KeyCode::V)]),
+            (Action::Pause, vec![Key(KeyCode::P)]),
+            (Action::Step, vec![Key(KeyCode::Period)]),
+            (Action::Slower, vec![Key(KeyCode::Minus)]),
+            (Action::Faster, vec![Key(KeyCode::Equals)]),
+            (Action::RewindBack, vec![Key(KeyCode::Left)]),
+            (Action::RewindForward, vec![Key(KeyCode::Right)]),
+            (Action::RewindFast, vec![Key(KeyCode::LShift)]),
+            (Action::Record, vec![Key(KeyCode::F2)]),
+            (Action::RecordFormat, vec![Key(KeyCode::F4)]),
+            (Action::RecordRate, vec![Key(KeyCode::F6)]),
+            (Action::RecordFilter, vec![Key(KeyCode::F7)]),
+            (Action::RecordTag, vec![Key(KeyCode::F8)]),
+            (Action::Replay, vec![Key(KeyCode::F3)]),
+            (Action::ReplayPlay, vec![Key(KeyCode::K)]),
+            (Action::ReplayBack, vec![Key(KeyCode::J)]),
+            (Action::ReplayForward, vec![Key(KeyCode::L)]),
+            (Action::ReplaySlower, vec![Key(KeyCode::LBracket)]),
+            (Action::ReplayFaster, vec![Key(KeyCode::RBracket)]),
+            (Action::SaveSnapshot, vec![Key(KeyCode::F5)]),
+            (Action::LoadSnapshot, vec![Key(KeyCode::F9)]),
+            (Action::ColorMode, vec![Key(KeyCode::C)]),
+            (Action::RenderPath, vec![Key(KeyCode::B)]),
+            (Action::TrailMode, vec![Key(KeyCode::T)]),
+            (Action::TrailSelect, vec![Key(KeyCode::Y)]),
+            (Action::HeatmapToggle, vec![Key(KeyCode::H)]),
+            (Action::HeatmapLayer, vec![Key(KeyCode::G)]),
+            (Action::HeatmapFasterDecay, vec![Key(KeyCode::Key9)]),
+            (Action::HeatmapSlowerDecay, vec![Key(KeyCode::Key0)]),
+            (Action::HeatmapClear, vec![Key(KeyCode::Delete)]),
+            (Action::HeatmapExport, vec![Key(KeyCode::F12)]),
+            (Action::Bindings, vec![Key(KeyCode::F1)]),
+        ])
+    }
+}
+
+impl ActionMap {
+    fn bindings_mut(&mut self, action: Action) -> &mut Vec<Binding> {
+        let index = match self.0.iter().position(|(other, _)| *other == action) {
+            Some(index) => index,
+            None => {
+                self.0.push((action, Vec::new()));
+                self.0.len() - 1
+            }
+        };
+
+        &mut self.0[index].1
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|err| format!("{:?}: {}", path, err))?;
+        Ok(Self::parse(path, &contents))
+    }
+
+    // Lines of `Action = Binding, Binding`, actions left out or on a malformed line keep
+    // their defaults
+    fn parse(path: &Path, contents: &str) -> Self {
+        let mut map = Self::default();
+
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let parsed = line.split_once('=').and_then(|(action, bindings)| {
+                let action = parse_variant::<Action>(action)?;
+                let bindings = bindings
+                    .split(',')
+                    .filter(|binding| !binding.trim().is_empty())
+                    .map(Binding::parse)
+                    .collect::<Option<Vec<_>>>()?;
+                Some((action, bindings))
+            });
+
+            match parsed {
+                Some((action, bindings)) => *map.bindings_mut(action) = bindings,
+                None => warn!(
+                    "{:?}:{}: skipped malformed binding {}",
+                    path,
+                    line_number + 1,
+                    line
+                ),
+            }
+        }
+
+        map
+    }
+
+    fn to_config(&self) -> String {
+        let mut contents = String::from("# Action = Key(..), Mouse(..), Gamepad(..)\n");
+
+        for (action, bindings) in self.0.iter() {
+            let bindings: Vec<String> = bindings
+                .iter()
+                .map(|binding| format!("{:?}", binding))
+                .collect();
+            contents += &format!("{:?} = {}\n", action, bindings.join(", "));
+        }
+
+        contents
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        fs::write(path, self.to_config())
+    }
+}
+
+// Actions triggered this frame
+#[derive(Resource, Default)]
+pub struct Actions {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+}
+
+impl Actions {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+}
+
+#[derive(Resource, Default)]
+struct Rebinding {
+    open: bool,
+    // Action waiting for an input, and whether it is added to the existing bindings
+    capturing: Option<(Action, bool)>,
+}
+
+impl Plugin for ActionPlugin {
+    fn build(&self, app: &mut App) {
+        let path = Path::new(BINDINGS_PATH);
+        let map = if path.exists() {
+            ActionMap::load(path).unwrap_or_else(|err| {
+                error!("Failed to load bindings {}", err);
+                ActionMap::default()
+            })
+        } else {
+            ActionMap::default()
+        };
+
+        app.insert_resource(map)
+            .init_resource::<Actions>()
+            .init_resource::<Rebinding>()
+            .add_system_to_stage(CoreStage::PreUpdate, update_actions.after(InputSystem))
+            .add_system(toggle_bindings)
+            .add_system(capture_binding)
+            .add_system(draw_bindings.after(toggle_bindings));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn update_actions(
+    mut actions: ResMut<Actions>,
+    mut egui_context: ResMut<EguiContext>,
+    map: Res<ActionMap>,
+    rebinding: Res<Rebinding>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+) {
+    actions.pressed.clear();
+    actions.just_pressed.clear();
+
+    // Nothing triggers while waiting for an input to bind
+    if rebinding.capturing.is_some() {
+        return;
+    }
+
+    // Clicks on egui windows are meant for them
+    let pointer_taken = egui_context.ctx_mut().wants_pointer_input();
+
+    for (action, bindings) in map.0.iter() {
+        for binding in bindings.iter() {
+            let (pressed, just_pressed) = match *binding {
+                Binding::Key(key) => (keys.pressed(key), keys.just_pressed(key)),
+                Binding::Mouse(_) if pointer_taken => (false, false),
+                Binding::Mouse(button) => (mouse.pressed(button), mouse.just_pressed(button)),
+                Binding::Gamepad(button_type) => gamepads
+                    .iter()
+                    .map(|gamepad| GamepadButton::new(gamepad, button_type))
+                    .fold((false, false), |(pressed, just_pressed), button| {
+                        (
+                            pressed || gamepad_buttons.pressed(button),
+                            just_pressed || gamepad_buttons.just_pressed(button),
+                        )
+                    }),
+            };
+
+            if pressed {
+                actions.pressed.insert(*action);
+            }
+            if just_pressed {
+                actions.just_pressed.insert(*action);
+            }
+        }
+    }
+}
+
+fn toggle_bindings(mut rebinding: ResMut<Rebinding>, actions: Res<Actions>) {
+    if actions.just_pressed(Action::Bindings) {
+        rebinding.open = !rebinding.open;
+    }
+}
+
+fn capture_binding(
+    mut rebinding: ResMut<Rebinding>,
+    mut map: ResMut<ActionMap>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+) {
+    let (action, append) = match rebinding.capturing {
+        Some(capturing) => capturing,
+        None => return,
+    };
+
+    if keys.just_pressed(KeyCode::Escape) {
+        rebinding.capturing = None;
+        return;
+    }
+
+    let binding = keys
+        .get_just_pressed()
+        .next()
+        .map(|key| Binding::Key(*key))
+        .or_else(|| {
+            mouse
+                .get_just_pressed()
+                .next()
+                .map(|button| Binding::Mouse(*button))
+        })
+        .or_else(|| {
+            gamepad_buttons
+                .get_just_pressed()
+                .next()
+                .map(|button| Binding::Gamepad(button.button_type))
+        });
+
+    if let Some(binding) = binding {
+        let bindings = map.bindings_mut(action);
+        if !append {
+            bindings.clear();
+        }
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+
+        rebinding.capturing = None;
+    }
+}
+
+fn draw_bindings(
+    mut egui_context: ResMut<EguiContext>,
+    mut rebinding: ResMut<Rebinding>,
+    mut map: ResMut<ActionMap>,
+) {
+    if !rebinding.open {
+        return;
+    }
+
+    let mut open = true;
+
+    egui::Window::new("Bindings")
+        .open(&mut open)
+        .vscroll(true)
+        .show(egui_context.ctx_mut(), |ui| {
+            egui::Grid::new("bindings").striped(true).show(ui, |ui| {
+                for (action, bindings) in map.0.iter_mut() {
+                    ui.label(format!("{:?}", action));
+
+                    if rebinding.capturing.map(|(capturing, _)| capturing) == Some(*action) {
+                        ui.label("Press a key or button, Escape to cancel");
+                    } else {
+                        let bindings: Vec<String> = bindings
+                            .iter()
+                            .map(|binding| format!("{:?}", binding))
+                            .collect();
+                        ui.label(bindings.join(", "));
+                    }
+
+                    if ui.button("Set").clicked() {
+                        rebinding.capturing = Some((*action, false));
+                    }
+                    if ui.button("Add").clicked() {
+                        rebinding.capturing = Some((*action, true));
+                    }
+                    if ui.button("Clear").clicked() {
+                        bindings.clear();
+                    }
+
+                    ui.end_row();
+                }
+            });
+
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    match map.save(Path::new(BINDINGS_PATH)) {
+                        Ok(_) => info!("Saved bindings to {}", BINDINGS_PATH),
+                        Err(err) => error!("Failed to write bindings {}: {}", BINDINGS_PATH, err),
+                    }
+                }
+
+                if ui.button("Defaults").clicked() {
+                    *map = ActionMap::default();
+                }
+            });
+        });
+
+    rebinding.open = open;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings_round_trip() {
+        for binding in [
+            Binding::Key(KeyCode::Space),
+            Binding::Key(KeyCode::F12),
+            Binding::Mouse(MouseButton::Left),
+            Binding::Mouse(MouseButton::Other(4)),
+            Binding::Gamepad(GamepadButtonType::South),
+            Binding::Gamepad(GamepadButtonType::Other(17)),
+        ] {
+            assert_eq!(Binding::parse(&format!("{:?}", binding)), Some(binding));
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_bindings() {
+        assert_eq!(Binding::parse("Key(NotAKey)"), None);
+        assert_eq!(Binding::parse("Mouse(Other(x))"), None);
+        assert_eq!(Binding::parse("Joystick(South)"), None);
+        assert_eq!(Binding::parse("Key Space"), None);
+    }
+
+    #[test]
+    fn config_round_trip() {
+        let mut map = ActionMap::default();
+        *map.bindings_mut(Action::Spawn) = vec![
+            Binding::Key(KeyCode::S),
+            Binding::Mouse(MouseButton::Other(4)),
+        ];
+        map.bindings_mut(Action::Pause).clear();
+
+        let parsed = ActionMap::parse(Path::new("test"), &map.to_config());
+        assert_eq!(parsed.0, map.0);
+    }
+
+    #[test]
+    fn malformed_lines_are_skipped() {
+        let contents =
+            "Spawn = Key(S)\nPause = Key(Nope)\nnot a binding\nStep = Key(N), Mouse(Right)\n";
+        let map = ActionMap::parse(Path::new("test"), contents);
+        let mut expected = ActionMap::default();
+
+        *expected.bindings_mut(Action::Spawn) = vec![Binding::Key(KeyCode::S)];
+        *expected.bindings_mut(Action::Step) =
+            vec![Binding::Key(KeyCode::N), Binding::Mouse(MouseButton::Right)];
+
+        assert_eq!(map.0, expected.0);
+    }
+}
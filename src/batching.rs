@@ -0,0 +1,184 @@
+use std::f32::consts::PI;
+
+use bevy::{
+    prelude::*,
+    render::{
+        mesh::PrimitiveTopology,
+        view::{NoFrustumCulling, RenderLayers},
+    },
+    sprite::Mesh2dHandle,
+};
+
+use crate::{
+    actions::{Action, Actions},
+    coloring::Tint,
+    hud::Hud,
+    vehicle::{Vehicle, LINE_WIDTH, VEHICLE_SIZE},
+};
+
+// Layer no camera looks at, used to hide the lyon shapes without touching `Visibility`
+const HIDDEN_LAYER: u8 = 31;
+const BATCH_Z: f32 = 900.0;
+
+pub struct BatchingPlugin;
+
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderPath {
+    // One tessellated lyon shape per vehicle
+    Lyon,
+    // All vehicles in a single mesh rebuilt from their transforms
+    Batched,
+}
+
+impl Default for RenderPath {
+    fn default() -> Self {
+        RenderPath::Lyon
+    }
+}
+
+#[derive(Component)]
+struct VehicleBatch;
+
+impl Plugin for BatchingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RenderPath>()
+            .add_startup_system(create_batch_mesh)
+            .add_system(render_path_controls)
+            .add_system(hide_shapes.after(render_path_controls))
+            .add_system(draw_batch.after(render_path_controls));
+    }
+}
+
+fn create_batch_mesh(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    set_batch_vertices(&mut mesh, Vec::new(), Vec::new());
+
+    commands
+        .spawn(ColorMesh2dBundle {
+            mesh: meshes.add(mesh).into(),
+            material: materials.add(ColorMaterial::from(Color::WHITE)),
+            transform: Transform::from_xyz(0.0, 0.0, BATCH_Z),
+            visibility: Visibility { is_visible: false },
+            ..default()
+        })
+        .insert(NoFrustumCulling)
+        .insert(VehicleBatch)
+        .insert(Name::new("Vehicle batch"));
+}
+
+fn set_batch_vertices(mesh: &mut Mesh, mut positions: Vec<[f32; 3]>, mut colors: Vec<[f32; 4]>) {
+    // Keep a degenerate triangle around so the vertex buffer is never empty
+    if positions.is_empty() {
+        positions = vec![[0.0; 3]; 3];
+        colors = vec![[0.0; 4]; 3];
+    }
+
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+}
+
+// Corners of a triangle pointing along +y, like the lyon shape
+fn triangle(radius: f32) -> [Vec2; 3] {
+    [0.0, 1.0, 2.0].map(|i| {
+        let angle = PI / 2.0 + i * 2.0 * PI / 3.0;
+        Vec2::new(angle.cos(), angle.sin()) * radius
+    })
+}
+
+fn render_path_controls(
+    mut render_path: ResMut<RenderPath>,
+    mut hud: ResMut<Hud>,
+    actions: Res<Actions>,
+) {
+    if !actions.just_pressed(Action::RenderPath) {
+        return;
+    }
+
+    *render_path = match *render_path {
+        RenderPath::Lyon => RenderPath::Batched,
+        RenderPath::Batched => RenderPath::Lyon,
+    };
+
+    match *render_path {
+        RenderPath::Lyon => hud.clear("render"),
+        RenderPath::Batched => hud.set("render", "Batched rendering"),
+    }
+}
+
+fn hide_shapes(
+    mut commands: Commands,
+    render_path: Res<RenderPath>,
+    shown_query: Query<Entity, (With<Vehicle>, Without<RenderLayers>)>,
+    hidden_query: Query<Entity, (With<Vehicle>, With<RenderLayers>)>,
+) {
+    match *render_path {
+        RenderPath::Lyon => hidden_query.for_each(|entity| {
+            commands.entity(entity).remove::<RenderLayers>();
+        }),
+        RenderPath::Batched => shown_query.for_each(|entity| {
+            commands
+                .entity(entity)
+                .insert(RenderLayers::layer(HIDDEN_LAYER));
+        }),
+    }
+}
+
+fn draw_batch(
+    render_path: Res<RenderPath>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut batch_query: Query<(&Mesh2dHandle, &mut Visibility), With<VehicleBatch>>,
+    vehicle_query: Query<(&Transform, &Visibility, Option<&Tint>), With<Vehicle>>,
+) {
+    let (handle, mut visibility) = batch_query.single_mut();
+
+    visibility.is_visible = *render_path == RenderPath::Batched;
+    if !visibility.is_visible {
+        return;
+    }
+
+    let outer = triangle(VEHICLE_SIZE + LINE_WIDTH / 2.0);
+    let inner = triangle(VEHICLE_SIZE - LINE_WIDTH / 2.0);
+    let heading = [
+        Vec2::new(-LINE_WIDTH / 2.0, 0.0),
+        Vec2::new(LINE_WIDTH / 2.0, 0.0),
+        Vec2::new(LINE_WIDTH / 2.0, VEHICLE_SIZE),
+        Vec2::new(-LINE_WIDTH / 2.0, 0.0),
+        Vec2::new(LINE_WIDTH / 2.0, VEHICLE_SIZE),
+        Vec2::new(-LINE_WIDTH / 2.0, VEHICLE_SIZE),
+    ];
+
+    let count = vehicle_query.iter().len();
+    let mut positions = Vec::with_capacity(count * 12);
+    let mut colors = Vec::with_capacity(count * 12);
+
+    for (transform, visibility, tint) in vehicle_query.iter() {
+        if !visibility.is_visible {
+            continue;
+        }
+
+        let (body, edge) = match tint {
+            Some(tint) => (
+                tint.body.as_linear_rgba_f32(),
+                tint.edge.as_linear_rgba_f32(),
+            ),
+            None => ([1.0; 4], [1.0; 4]),
+        };
+
+        // Outline, then the body on top of it, then the heading line
+        for (corners, color) in [(&outer[..], edge), (&inner[..], body), (&heading[..], edge)] {
+            for corner in corners {
+                let position = transform.transform_point(corner.extend(0.0));
+                positions.push([position.x, position.y, 0.0]);
+                colors.push(color);
+            }
+        }
+    }
+
+    if let Some(mesh) = meshes.get_mut(&handle.0) {
+        set_batch_vertices(mesh, positions, colors);
+    }
+}
@@ -0,0 +1,283 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use rand::prelude::*;
+
+use crate::{
+    actions::{Action, Actions},
+    cursor_world_position,
+    hud::Hud,
+    replay::not_replaying,
+    rng::SimRng,
+    target::{Target, TargetMode, TargetMotion},
+    teams::TEAM_COUNT,
+    vehicle::{Genome, SpawnVehicle, Team, VehicleProfile},
+    MainCamera,
+};
+
+// Vehicles per second while the spawn action is held
+const BRUSH_RATES: [f32; 7] = [1.0, 5.0, 10.0, 30.0, 60.0, 120.0, 240.0];
+const BRUSH_DEFAULT_RATE: usize = 4;
+const BRUSH_RADIUS: f32 = 40.0;
+const BRUSH_SPEED: f32 = 150.0;
+
+pub struct BrushPlugin;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BrushShape {
+    Point,
+    Disc,
+    Ring,
+    Line,
+}
+
+impl BrushShape {
+    fn next(&self) -> Self {
+        match self {
+            BrushShape::Point => BrushShape::Disc,
+            BrushShape::Disc => BrushShape::Ring,
+            BrushShape::Ring => BrushShape::Line,
+            BrushShape::Line => BrushShape::Point,
+        }
+    }
+
+    // Random spawn position relative to the brush center
+    fn offset(&self, rng: &mut SimRng) -> Vec2 {
+        match self {
+            BrushShape::Point => Vec2::ZERO,
+            // The square root keeps the disc evenly filled instead of crowding the center
+            BrushShape::Disc => random_direction(rng) * BRUSH_RADIUS * rng.gen::<f32>().sqrt(),
+            BrushShape::Ring => random_direction(rng) * BRUSH_RADIUS,
+            BrushShape::Line => Vec2::new(rng.gen_range(-BRUSH_RADIUS..BRUSH_RADIUS), 0.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BrushVelocity {
+    Zero,
+    Random,
+    // Away from the brush center
+    Radial,
+    TowardTarget,
+}
+
+impl BrushVelocity {
+    fn next(&self) -> Self {
+        match self {
+            BrushVelocity::Zero => BrushVelocity::Random,
+            BrushVelocity::Random => BrushVelocity::Radial,
+            BrushVelocity::Radial => BrushVelocity::TowardTarget,
+            BrushVelocity::TowardTarget => BrushVelocity::Zero,
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct SpawnBrush {
+    pub shape: BrushShape,
+    pub velocity: BrushVelocity,
+    pub profile: VehicleProfile,
+    pub team: Team,
+    rate: usize,
+}
+
+impl Default for SpawnBrush {
+    fn default() -> Self {
+        Self {
+            shape: BrushShape::Point,
+            velocity: BrushVelocity::Zero,
+            profile: VehicleProfile::Secondary,
+            // Apart from the startup flock, so team rules can tell them apart
+            team: Team(1),
+            rate: BRUSH_DEFAULT_RATE,
+        }
+    }
+}
+
+impl SpawnBrush {
+    pub fn rate(&self) -> f32 {
+        BRUSH_RATES[self.rate]
+    }
+}
+
+// Where a resting finger paints, set by the touch input
+#[derive(Resource, Default)]
+pub struct TouchBrush(pub Option<Vec2>);
+
+impl Plugin for BrushPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SpawnBrush>()
+            .init_resource::<TouchBrush>()
+            .add_system(brush_controls)
+            .add_system(paint.with_run_criteria(not_replaying).after(brush_controls));
+    }
+}
+
+fn random_direction(rng: &mut SimRng) -> Vec2 {
+    let angle = rng.gen_range(0.0..2.0 * PI);
+    Vec2::new(angle.cos(), angle.sin())
+}
+
+fn brush_controls(mut brush: ResMut<SpawnBrush>, mut hud: ResMut<Hud>, actions: Res<Actions>) {
+    if actions.just_pressed(Action::BrushShape) {
+        brush.shape = brush.shape.next();
+    }
+
+    if actions.just_pressed(Action::BrushVelocity) {
+        brush.velocity = brush.velocity.next();
+    }
+
+    if actions.just_pressed(Action::BrushProfile) {
+        brush.profile = brush.profile.next();
+    }
+
+    if actions.just_pressed(Action::BrushTeam) {
+        brush.team = Team((brush.team.0 + 1) % TEAM_COUNT as u8);
+    }
+
+    if actions.just_pressed(Action::BrushSlower) {
+        brush.rate = brush.rate.saturating_sub(1);
+    }
+
+    if actions.just_pressed(Action::BrushFaster) {
+        brush.rate = (brush.rate + 1).min(BRUSH_RATES.len() - 1);
+    }
+
+    if brush.is_changed() {
+        hud.set(
+            "brush",
+            format!(
+                "Brush {:?}, {:?} velocity, {:?}, team {}, {}/s",
+                brush.shape,
+                brush.velocity,
+                brush.profile,
+                brush.team.0,
+                brush.rate()
+            ),
+        );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn paint(
+    mut spawn_events: EventWriter<SpawnVehicle>,
+    mut rng: ResMut<SimRng>,
+    // Vehicles owed from previous frames at low rates
+    mut pending: Local<f32>,
+    mut painting: Local<bool>,
+    brush: Res<SpawnBrush>,
+    touch: Res<TouchBrush>,
+    actions: Res<Actions>,
+    motion: Res<TargetMotion>,
+    target_query: Query<&Transform, With<Target>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    windows: Res<Windows>,
+    time: Res<Time>,
+) {
+    if touch.0.is_none() && !actions.pressed(Action::Spawn) {
+        *pending = 0.0;
+        *painting = false;
+        return;
+    }
+
+    let window = windows.get_primary().unwrap();
+    let (camera, camera_transform) = camera_query.single();
+
+    let target = target_query
+        .get_single()
+        .ok()
+        .map(|transform| transform.translation.truncate());
+
+    // A gamepad has no cursor, so vehicles appear at the target it steers
+    let center = if touch.0.is_some() {
+        touch.0
+    } else if motion.mode == TargetMode::Gamepad {
+        target
+    } else {
+        cursor_world_position(window, camera, camera_transform)
+    };
+
+    let center = match center {
+        Some(center) => center,
+        None => return,
+    };
+
+    // The first vehicle appears as soon as painting starts
+    if !*painting {
+        *painting = true;
+        *pending = 1.0;
+    } else {
+        *pending += brush.rate() * time.delta_seconds();
+    }
+
+    while *pending >= 1.0 {
+        *pending -= 1.0;
+
+        let offset = brush.shape.offset(&mut rng);
+        let position = center + offset;
+
+        let direction = match brush.velocity {
+            BrushVelocity::Zero => Vec2::ZERO,
+            BrushVelocity::Random => random_direction(&mut rng),
+            BrushVelocity::Radial if offset == Vec2::ZERO => random_direction(&mut rng),
+            BrushVelocity::Radial => offset.normalize(),
+            BrushVelocity::TowardTarget => target
+                .map(|target| (target - position).normalize_or_zero())
+                .unwrap_or_default(),
+        };
+
+        spawn_events.send(SpawnVehicle {
+            position,
+            velocity: direction * BRUSH_SPEED,
+            profile: brush.profile,
+            team: brush.team,
+            genome: Genome::default(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offsets(shape: BrushShape) -> Vec<Vec2> {
+        let mut rng = SimRng::default();
+        rng.seed(7);
+        (0..1000).map(|_| shape.offset(&mut rng)).collect()
+    }
+
+    #[test]
+    fn point_stays_at_the_center() {
+        assert!(offsets(BrushShape::Point)
+            .iter()
+            .all(|offset| *offset == Vec2::ZERO));
+    }
+
+    #[test]
+    fn disc_fills_the_radius() {
+        let offsets = offsets(BrushShape::Disc);
+
+        assert!(offsets.iter().all(|offset| offset.length() <= BRUSH_RADIUS));
+        // Evenly filled, so about a quarter lands within half the radius
+        let inner = offsets
+            .iter()
+            .filter(|offset| offset.length() <= BRUSH_RADIUS / 2.0)
+            .count();
+        assert!((150..350).contains(&inner), "{}", inner);
+    }
+
+    #[test]
+    fn ring_stays_on_the_radius() {
+        assert!(offsets(BrushShape::Ring)
+            .iter()
+            .all(|offset| (offset.length() - BRUSH_RADIUS).abs() < 1e-3));
+    }
+
+    #[test]
+    fn line_stays_horizontal() {
+        assert!(offsets(BrushShape::Line)
+            .iter()
+            .all(|offset| offset.y == 0.0 && offset.x.abs() <= BRUSH_RADIUS));
+    }
+}
@@ -0,0 +1,151 @@
+use bevy::{ecs::schedule::ShouldRun, prelude::*};
+
+use crate::{
+    actions::{Action, Actions},
+    hud::Hud,
+    replay::Replay,
+};
+
+const CLOCK_SCALES: [f32; 9] = [0.1, 0.25, 0.5, 1.0, 1.5, 2.0, 3.0, 5.0, 10.0];
+const CLOCK_DEFAULT_SCALE: usize = 3;
+
+pub struct ClockPlugin;
+
+// Simulation time, which runs separately from the wall clock so the flock can
+// be paused, stepped and sped up without touching rendering or input
+#[derive(Resource)]
+pub struct SimClock {
+    pub paused: bool,
+    scale: usize,
+    step_requested: bool,
+    running: bool,
+    delta: f32,
+    elapsed: f32,
+}
+
+impl Default for SimClock {
+    fn default() -> Self {
+        Self {
+            paused: false,
+            scale: CLOCK_DEFAULT_SCALE,
+            step_requested: false,
+            running: true,
+            delta: 0.0,
+            elapsed: 0.0,
+        }
+    }
+}
+
+impl SimClock {
+    pub fn scale(&self) -> f32 {
+        CLOCK_SCALES[self.scale]
+    }
+
+    pub fn slower(&mut self) {
+        self.scale = self.scale.saturating_sub(1);
+    }
+
+    // Picks the preset closest to `scale`
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = CLOCK_SCALES
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| (*a - scale).abs().total_cmp(&(*b - scale).abs()))
+            .map_or(CLOCK_DEFAULT_SCALE, |(index, _)| index);
+    }
+
+    pub fn faster(&mut self) {
+        self.scale = (self.scale + 1).min(CLOCK_SCALES.len() - 1);
+    }
+
+    // Advance a single frame while paused
+    pub fn step(&mut self) {
+        self.paused = true;
+        self.step_requested = true;
+    }
+
+    // Pauses and also stops the current frame, for systems running after `tick`
+    pub fn freeze(&mut self) {
+        self.paused = true;
+        self.running = false;
+        self.elapsed -= self.delta;
+        self.delta = 0.0;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    // Scaled time advanced by the simulation this frame
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta
+    }
+
+    pub fn elapsed_seconds(&self) -> f32 {
+        self.elapsed
+    }
+
+    pub fn set_elapsed_seconds(&mut self, elapsed: f32) {
+        self.elapsed = elapsed;
+    }
+}
+
+impl Plugin for ClockPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SimClock>()
+            .add_system_to_stage(CoreStage::PreUpdate, tick)
+            .add_system(controls)
+            .add_system(status);
+    }
+}
+
+pub fn simulating(clock: Res<SimClock>, replay: Res<Replay>) -> ShouldRun {
+    if clock.is_running() && !replay.active {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+pub fn tick(mut clock: ResMut<SimClock>, time: Res<Time>) {
+    clock.running = !clock.paused || clock.step_requested;
+    clock.step_requested = false;
+
+    clock.delta = if clock.running {
+        time.delta_seconds() * clock.scale()
+    } else {
+        0.0
+    };
+    clock.elapsed += clock.delta;
+}
+
+fn controls(mut clock: ResMut<SimClock>, actions: Res<Actions>) {
+    if actions.just_pressed(Action::Pause) {
+        clock.paused = !clock.paused;
+    }
+
+    if actions.just_pressed(Action::Step) {
+        clock.step();
+    }
+
+    if actions.just_pressed(Action::Slower) {
+        clock.slower();
+    }
+
+    if actions.just_pressed(Action::Faster) {
+        clock.faster();
+    }
+}
+
+fn status(clock: Res<SimClock>, mut hud: ResMut<Hud>) {
+    let state = if clock.paused { "Paused" } else { "Running" };
+    hud.set(
+        "clock",
+        format!(
+            "{} {:>5}x  t={:.1}s",
+            state,
+            clock.scale(),
+            clock.elapsed_seconds()
+        ),
+    );
+}
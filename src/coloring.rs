@@ -0,0 +1,400 @@
+use std::{
+    collections::{HashMap, HashSet},
+    f32::consts::PI,
+};
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use bevy_prototype_lyon::prelude::*;
+
+use crate::{
+    actions::{Action, Actions},
+    batching::RenderPath,
+    hud::Hud,
+    leaders::{Leader, LEADER_BODY_COLOR},
+    teams::{team_color, TEAM_COUNT},
+    vehicle::{
+        Steering, SteeringForce, Team, Vehicle, VehicleColors, VehicleSystem, Velocity,
+        VEHICLE_MAX_SPEED,
+    },
+    world::WALL_MARGIN,
+};
+
+// Vehicles closer than this end up in the same cluster
+const CLUSTER_DIST: f32 = 40.0;
+const CLUSTER_DIST_SQ: f32 = CLUSTER_DIST * CLUSTER_DIST;
+// Neighbour count at which the gradient tops out
+const NEIGHBOUR_MAX: usize = 12;
+const GOLDEN_ANGLE: f32 = 137.507_77;
+
+const LEGEND_STEPS: usize = 5;
+const LEGEND_SWATCH_SIZE: f32 = 12.0;
+
+pub struct ColoringPlugin;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorMode {
+    // The colors the vehicle was spawned with
+    Fixed,
+    Speed,
+    Heading,
+    Neighbours,
+    Cluster,
+    Steering,
+    Team,
+}
+
+impl ColorMode {
+    fn next(&self) -> Self {
+        match self {
+            ColorMode::Fixed => ColorMode::Speed,
+            ColorMode::Speed => ColorMode::Heading,
+            ColorMode::Heading => ColorMode::Neighbours,
+            ColorMode::Neighbours => ColorMode::Cluster,
+            ColorMode::Cluster => ColorMode::Steering,
+            ColorMode::Steering => ColorMode::Team,
+            ColorMode::Team => ColorMode::Fixed,
+        }
+    }
+}
+
+// Colors the vehicle is currently drawn with
+#[derive(Component, Clone, Copy)]
+pub struct Tint {
+    pub body: Color,
+    pub edge: Color,
+}
+
+#[derive(Resource)]
+pub struct Coloring {
+    pub mode: ColorMode,
+    clusters: usize,
+}
+
+impl Default for Coloring {
+    fn default() -> Self {
+        Self {
+            mode: ColorMode::Fixed,
+            clusters: 0,
+        }
+    }
+}
+
+impl Plugin for ColoringPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Coloring>()
+            .add_system(coloring_controls)
+            .add_system(
+                color_vehicles
+                    .after(coloring_controls)
+                    .after(VehicleSystem::Steer),
+            )
+            .add_system(draw_legend.after(color_vehicles));
+    }
+}
+
+// Blue when low, red when high
+fn gradient(t: f32) -> Color {
+    Color::hsl(240.0 * (1.0 - t.clamp(0.0, 1.0)), 0.9, 0.55)
+}
+
+fn heading_color(velocity: Vec2) -> Color {
+    let angle = velocity.y.atan2(velocity.x);
+    Color::hsl((angle + PI) / (2.0 * PI) * 360.0, 0.9, 0.55)
+}
+
+fn cluster_color(id: u32) -> Color {
+    Color::hsl((id as f32 * GOLDEN_ANGLE) % 360.0, 0.8, 0.6)
+}
+
+fn steering_color(force: Option<SteeringForce>) -> Color {
+    match force {
+        Some(SteeringForce::Separation) => Color::RED,
+        Some(SteeringForce::Alignment) => Color::CYAN,
+        Some(SteeringForce::Cohesion) => Color::LIME_GREEN,
+        Some(SteeringForce::View) => Color::YELLOW,
+        Some(SteeringForce::Target) => Color::FUCHSIA,
+        Some(SteeringForce::Flee) => Color::PURPLE,
+        Some(SteeringForce::Wander) => Color::ORANGE,
+        Some(SteeringForce::Bounds) => Color::WHITE,
+        Some(SteeringForce::Pursue) => Color::MAROON,
+        Some(SteeringForce::Evade) => Color::TEAL,
+        Some(SteeringForce::Forage) => Color::GREEN,
+        Some(SteeringForce::Follow) => Color::GOLD,
+        Some(SteeringForce::Formation) => Color::SALMON,
+        None => Color::GRAY,
+    }
+}
+
+fn find(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+fn cluster_cell(position: Vec2) -> (i32, i32) {
+    let cell = (position / CLUSTER_DIST).floor();
+    (cell.x as i32, cell.y as i32)
+}
+
+// Groups vehicles that are connected through chains of close neighbours, each
+// cluster is identified by its lowest entity index so colors stay stable.
+// Cells are as wide as the cluster distance, so only neighbouring cells are compared
+fn clusters(positions: &[(Entity, Vec2)]) -> HashMap<Entity, u32> {
+    let mut parents: Vec<usize> = (0..positions.len()).collect();
+
+    let mut grid: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+    for (i, (_, position)) in positions.iter().enumerate() {
+        grid.entry(cluster_cell(*position)).or_default().push(i);
+    }
+
+    for (i, (_, position)) in positions.iter().enumerate() {
+        let (x, y) = cluster_cell(*position);
+
+        for cell in (x - 1..=x + 1).flat_map(|x| (y - 1..=y + 1).map(move |y| (x, y))) {
+            for &j in grid.get(&cell).into_iter().flatten() {
+                if j > i && position.distance_squared(positions[j].1) <= CLUSTER_DIST_SQ {
+                    let a = find(&mut parents, i);
+                    let b = find(&mut parents, j);
+                    parents[a.max(b)] = a.min(b);
+                }
+            }
+        }
+    }
+
+    let mut ids: HashMap<usize, u32> = HashMap::new();
+    for (i, (entity, _)) in positions.iter().enumerate() {
+        let root = find(&mut parents, i);
+        let id = ids.entry(root).or_insert(u32::MAX);
+        *id = (*id).min(entity.index());
+    }
+
+    positions
+        .iter()
+        .enumerate()
+        .map(|(i, (entity, _))| (*entity, ids[&find(&mut parents, i)]))
+        .collect()
+}
+
+fn set_colors(draw_mode: &mut Mut<DrawMode>, body: Color, edge: Color) {
+    if let DrawMode::Outlined {
+        fill_mode,
+        outline_mode,
+    } = &**draw_mode
+    {
+        // Changing the draw mode re-tessellates the shape, so leave it alone if nothing changed
+        if fill_mode.color == body && outline_mode.color == edge {
+            return;
+        }
+    }
+
+    if let DrawMode::Outlined {
+        fill_mode,
+        outline_mode,
+    } = &mut **draw_mode
+    {
+        fill_mode.color = body;
+        outline_mode.color = edge;
+    }
+}
+
+fn coloring_controls(mut coloring: ResMut<Coloring>, mut hud: ResMut<Hud>, actions: Res<Actions>) {
+    if actions.just_pressed(Action::ColorMode) {
+        coloring.mode = coloring.mode.next();
+    }
+
+    if coloring.mode == ColorMode::Fixed {
+        hud.clear("coloring");
+    } else {
+        hud.set("coloring", format!("Color by {:?}", coloring.mode));
+    }
+}
+
+fn color_vehicles(
+    mut coloring: ResMut<Coloring>,
+    render_path: Res<RenderPath>,
+    mut vehicle_query: Query<
+        (
+            Entity,
+            &mut Tint,
+            &mut DrawMode,
+            &Transform,
+            &Velocity,
+            &Steering,
+            &VehicleColors,
+            &Team,
+            Option<&Leader>,
+        ),
+        With<Vehicle>,
+    >,
+) {
+    let mode = coloring.mode;
+
+    let clusters = if mode == ColorMode::Cluster {
+        let positions: Vec<(Entity, Vec2)> = vehicle_query
+            .iter()
+            .map(|(entity, _, _, transform, ..)| (entity, transform.translation.truncate()))
+            .collect();
+        clusters(&positions)
+    } else {
+        HashMap::new()
+    };
+
+    coloring.clusters = clusters.values().collect::<HashSet<_>>().len();
+
+    vehicle_query.for_each_mut(
+        |(entity, mut tint, mut draw_mode, _, velocity, steering, colors, team, leader)| {
+            let (body, edge) = match mode {
+                ColorMode::Fixed if leader.is_some() => (LEADER_BODY_COLOR, colors.edge),
+                ColorMode::Fixed => (colors.body, colors.edge),
+                mode => {
+                    let color = match mode {
+                        ColorMode::Speed => gradient(velocity.length() / VEHICLE_MAX_SPEED),
+                        ColorMode::Heading => heading_color(velocity.0),
+                        ColorMode::Neighbours => {
+                            gradient(steering.neighbours as f32 / NEIGHBOUR_MAX as f32)
+                        }
+                        ColorMode::Cluster => {
+                            cluster_color(clusters.get(&entity).copied().unwrap_or(0))
+                        }
+                        ColorMode::Team => team_color(*team),
+                        _ => steering_color(steering.dominant),
+                    };
+                    (color, color)
+                }
+            };
+
+            tint.body = body;
+            tint.edge = edge;
+
+            // The batched renderer reads the tint directly, so the shapes are only
+            // touched when they are actually drawn
+            if *render_path == RenderPath::Lyon {
+                set_colors(&mut draw_mode, body, edge);
+            }
+        },
+    );
+}
+
+fn egui_color(color: Color) -> egui::Color32 {
+    let [r, g, b, a] = color.as_rgba_f32();
+    egui::Color32::from_rgba_unmultiplied(
+        (r * 255.0) as u8,
+        (g * 255.0) as u8,
+        (b * 255.0) as u8,
+        (a * 255.0) as u8,
+    )
+}
+
+fn swatch(ui: &mut egui::Ui, color: Color) {
+    let (rect, _) = ui.allocate_exact_size(
+        egui::vec2(LEGEND_SWATCH_SIZE, LEGEND_SWATCH_SIZE),
+        egui::Sense::hover(),
+    );
+    ui.painter().rect_filled(rect, 2.0, egui_color(color));
+}
+
+fn legend_label(ui: &mut egui::Ui, text: impl Into<String>) {
+    ui.label(
+        egui::RichText::new(text)
+            .color(egui::Color32::WHITE)
+            .monospace(),
+    );
+}
+
+fn gradient_legend(ui: &mut egui::Ui, low: &str, high: &str) {
+    ui.horizontal(|ui| {
+        legend_label(ui, low);
+        for step in 0..LEGEND_STEPS {
+            swatch(ui, gradient(step as f32 / (LEGEND_STEPS - 1) as f32));
+        }
+        legend_label(ui, high);
+    });
+}
+
+fn draw_legend(mut egui_context: ResMut<EguiContext>, coloring: Res<Coloring>) {
+    if coloring.mode == ColorMode::Fixed {
+        return;
+    }
+
+    egui::Area::new("legend")
+        .anchor(
+            egui::Align2::RIGHT_TOP,
+            egui::vec2(-WALL_MARGIN, WALL_MARGIN),
+        )
+        .interactable(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            legend_label(ui, format!("{:?}", coloring.mode));
+
+            match coloring.mode {
+                ColorMode::Fixed => {}
+                ColorMode::Speed => {
+                    gradient_legend(ui, "0", &format!("{}", VEHICLE_MAX_SPEED));
+                }
+                ColorMode::Heading => {
+                    for (name, direction) in [
+                        ("East", Vec2::X),
+                        ("North", Vec2::Y),
+                        ("West", Vec2::NEG_X),
+                        ("South", Vec2::NEG_Y),
+                    ] {
+                        ui.horizontal(|ui| {
+                            swatch(ui, heading_color(direction));
+                            legend_label(ui, name);
+                        });
+                    }
+                }
+                ColorMode::Neighbours => {
+                    gradient_legend(ui, "0", &format!("{}+", NEIGHBOUR_MAX));
+                }
+                ColorMode::Cluster => {
+                    legend_label(ui, format!("{} clusters", coloring.clusters));
+                }
+                ColorMode::Steering => {
+                    for force in SteeringForce::ALL {
+                        ui.horizontal(|ui| {
+                            swatch(ui, steering_color(Some(force)));
+                            legend_label(ui, format!("{:?}", force));
+                        });
+                    }
+                }
+                ColorMode::Team => {
+                    for team in 0..TEAM_COUNT {
+                        ui.horizontal(|ui| {
+                            swatch(ui, team_color(Team(team as u8)));
+                            legend_label(ui, format!("Team {}", team));
+                        });
+                    }
+                }
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chains_of_neighbours_form_one_cluster() {
+        // A chain crossing several grid cells, and a vehicle just out of its reach
+        let positions: Vec<(Entity, Vec2)> = [
+            (5, Vec2::new(-30.0, 0.0)),
+            (3, Vec2::new(5.0, 0.0)),
+            (8, Vec2::new(40.0, 10.0)),
+            (4, Vec2::new(40.0, 50.0)),
+            (1, Vec2::new(40.0, 90.5)),
+        ]
+        .into_iter()
+        .map(|(index, position)| (Entity::from_raw(index), position))
+        .collect();
+
+        let clusters = clusters(&positions);
+
+        for index in [5, 3, 8, 4] {
+            assert_eq!(clusters[&Entity::from_raw(index)], 3);
+        }
+        assert_eq!(clusters[&Entity::from_raw(1)], 1);
+    }
+}
@@ -0,0 +1,322 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use rand::prelude::*;
+
+use crate::{
+    actions::{Action, Actions},
+    brush::SpawnBrush,
+    clock::{simulating, SimClock},
+    cursor_world_position,
+    hud::Hud,
+    placeables::{hovered, scroll_placeables, PlaceableScroll},
+    rng::SimRng,
+    target::Target,
+    vehicle::{Genome, SpawnVehicle, Team, VehicleProfile, VehicleSystem},
+    MainCamera,
+};
+
+// Vehicles per second
+const EMITTER_RATE: f32 = 5.0;
+const EMITTER_MIN_RATE: f32 = 0.5;
+const EMITTER_MAX_RATE: f32 = 100.0;
+const EMITTER_RATE_STEP: f32 = 1.1;
+const EMITTER_SCROLL_TURN: f32 = PI / 16.0;
+const EMITTER_SPEED: f32 = 150.0;
+// Vehicles leave within this angle of the emitter direction
+const EMITTER_SPREAD: f32 = PI / 8.0;
+const EMITTER_RADIUS: f32 = 12.0;
+// Reaches to the tip of the aiming line
+pub const EMITTER_HOVER_RADIUS: f32 = EMITTER_RADIUS * 2.0;
+const EMITTER_LINE_WIDTH: f32 = 2.0;
+const EMITTER_Z: f32 = 9.0;
+
+// Waves emit for the first part of every period, bursts release a whole
+// period's worth of vehicles at once
+const EMITTER_WAVE_PERIOD: f32 = 6.0;
+const EMITTER_WAVE_LENGTH: f32 = 2.0;
+const EMITTER_BURST_PERIOD: f32 = 3.0;
+
+pub struct EmitterPlugin;
+
+#[derive(Reflect, FromReflect, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmitterPattern {
+    Continuous,
+    Waves,
+    Bursts,
+}
+
+impl EmitterPattern {
+    fn next(&self) -> Self {
+        match self {
+            EmitterPattern::Continuous => EmitterPattern::Waves,
+            EmitterPattern::Waves => EmitterPattern::Bursts,
+            EmitterPattern::Bursts => EmitterPattern::Continuous,
+        }
+    }
+}
+
+#[derive(Component, Reflect, FromReflect, Clone)]
+pub struct Emitter {
+    pub rate: f32,
+    // Radians from the x axis
+    pub angle: f32,
+    pub profile: VehicleProfile,
+    pub team: Team,
+    pub pattern: EmitterPattern,
+    pub emitted: usize,
+    elapsed: f32,
+    pending: f32,
+}
+
+impl Emitter {
+    pub fn new(
+        rate: f32,
+        angle: f32,
+        profile: VehicleProfile,
+        team: Team,
+        pattern: EmitterPattern,
+    ) -> Self {
+        Self {
+            rate,
+            angle,
+            profile,
+            team,
+            pattern,
+            emitted: 0,
+            elapsed: 0.0,
+            pending: 0.0,
+        }
+    }
+
+    // Advances the emitter and returns how many vehicles it releases
+    fn advance(&mut self, delta: f32) -> usize {
+        let previous = self.elapsed;
+        self.elapsed += delta;
+
+        match self.pattern {
+            EmitterPattern::Continuous => self.pending += self.rate * delta,
+            EmitterPattern::Waves => {
+                if self.elapsed % EMITTER_WAVE_PERIOD < EMITTER_WAVE_LENGTH {
+                    self.pending += self.rate * delta;
+                }
+            }
+            EmitterPattern::Bursts => {
+                let bursts = (self.elapsed / EMITTER_BURST_PERIOD).floor()
+                    - (previous / EMITTER_BURST_PERIOD).floor();
+                self.pending += bursts * self.rate * EMITTER_BURST_PERIOD;
+            }
+        }
+
+        let count = self.pending.floor();
+        self.pending -= count;
+        self.emitted += count as usize;
+        count as usize
+    }
+}
+
+impl Plugin for EmitterPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(place_emitters)
+            .add_system(adjust_emitters.after(scroll_placeables))
+            .add_system(emitter_status)
+            .add_system(
+                emit.with_run_criteria(simulating)
+                    .before(VehicleSystem::Steer),
+            );
+    }
+}
+
+pub fn spawn_emitter(commands: &mut Commands, position: Vec2, emitter: Emitter) -> Entity {
+    let circle = shapes::Circle {
+        radius: EMITTER_RADIUS,
+        ..shapes::Circle::default()
+    };
+
+    // Drawn along the x axis and rotated towards the emitter direction
+    let line = shapes::Line(Vec2::ZERO, Vec2::new(EMITTER_RADIUS * 2.0, 0.0));
+
+    let mut fill = emitter.profile.colors().edge;
+    fill.set_a(0.4);
+
+    commands
+        .spawn(GeometryBuilder::new().add(&circle).add(&line).build(
+            DrawMode::Outlined {
+                fill_mode: FillMode::color(fill),
+                outline_mode: StrokeMode::new(emitter.profile.colors().edge, EMITTER_LINE_WIDTH),
+            },
+            Transform {
+                translation: position.extend(EMITTER_Z),
+                rotation: Quat::from_rotation_z(emitter.angle),
+                ..Default::default()
+            },
+        ))
+        .insert(Name::new("Emitter"))
+        .insert(emitter)
+        .id()
+}
+
+// Places an emitter aimed at the target, or removes the one under the cursor
+fn place_emitters(
+    mut commands: Commands,
+    mut emitter_query: Query<(Entity, &Transform, &mut Emitter)>,
+    target_query: Query<&Transform, With<Target>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    windows: Res<Windows>,
+    actions: Res<Actions>,
+    brush: Res<SpawnBrush>,
+) {
+    let window = windows.get_primary().unwrap();
+    let (camera, camera_transform) = camera_query.single();
+
+    let world_pos = match cursor_world_position(window, camera, camera_transform) {
+        Some(world_pos) => world_pos,
+        None => return,
+    };
+
+    let hovered = hovered(
+        world_pos,
+        emitter_query
+            .iter()
+            .map(|(entity, transform, _)| (entity, transform, EMITTER_HOVER_RADIUS)),
+    );
+
+    if actions.just_pressed(Action::PlaceEmitter) {
+        match hovered {
+            Some(entity) => commands.entity(entity).despawn(),
+            None => {
+                let direction = target_query
+                    .get_single()
+                    .map(|transform| transform.translation.truncate() - world_pos)
+                    .unwrap_or(Vec2::X);
+
+                spawn_emitter(
+                    &mut commands,
+                    world_pos,
+                    Emitter::new(
+                        EMITTER_RATE,
+                        direction.y.atan2(direction.x),
+                        brush.profile,
+                        brush.team,
+                        EmitterPattern::Continuous,
+                    ),
+                );
+            }
+        }
+    }
+
+    if actions.just_pressed(Action::EmitterPattern) {
+        if let Some(Ok((_, _, mut emitter))) = hovered.map(|entity| emitter_query.get_mut(entity)) {
+            emitter.pattern = emitter.pattern.next();
+        }
+    }
+}
+
+fn adjust_emitters(
+    mut emitter_query: Query<(&mut Transform, &mut Emitter)>,
+    actions: Res<Actions>,
+    scroll: Res<PlaceableScroll>,
+) {
+    let (entity, scroll) = match scroll.0 {
+        Some(scroll) => scroll,
+        None => return,
+    };
+
+    if let Ok((mut transform, mut emitter)) = emitter_query.get_mut(entity) {
+        if actions.pressed(Action::Strength) {
+            emitter.rate = (emitter.rate * EMITTER_RATE_STEP.powf(scroll))
+                .clamp(EMITTER_MIN_RATE, EMITTER_MAX_RATE);
+        } else {
+            emitter.angle += scroll * EMITTER_SCROLL_TURN;
+            transform.rotation = Quat::from_rotation_z(emitter.angle);
+        }
+    }
+}
+
+fn emit(
+    mut spawn_events: EventWriter<SpawnVehicle>,
+    mut rng: ResMut<SimRng>,
+    mut emitter_query: Query<(&Transform, &mut Emitter)>,
+    clock: Res<SimClock>,
+) {
+    for (transform, mut emitter) in emitter_query.iter_mut() {
+        let count = emitter.advance(clock.delta_seconds());
+
+        for _ in 0..count {
+            let angle = emitter.angle + rng.gen_range(-EMITTER_SPREAD..EMITTER_SPREAD);
+            let direction = Vec2::new(angle.cos(), angle.sin());
+
+            // Spread over the mouth of the emitter so bursts do not stack up
+            let offset = direction.perp() * rng.gen_range(-EMITTER_RADIUS..EMITTER_RADIUS);
+
+            spawn_events.send(SpawnVehicle {
+                position: transform.translation.truncate() + direction * EMITTER_RADIUS + offset,
+                velocity: direction * EMITTER_SPEED,
+                profile: emitter.profile,
+                team: emitter.team,
+                genome: Genome::default(),
+            });
+        }
+    }
+}
+
+fn emitter_status(mut hud: ResMut<Hud>, emitter_query: Query<&Emitter>) {
+    if emitter_query.is_empty() {
+        hud.clear("emitters");
+        return;
+    }
+
+    let emitters: Vec<String> = emitter_query
+        .iter()
+        .map(|emitter| {
+            format!(
+                "{:?} {:.1}/s {}",
+                emitter.pattern, emitter.rate, emitter.emitted
+            )
+        })
+        .collect();
+
+    hud.set("emitters", format!("Emitters {}", emitters.join(", ")));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emitter(pattern: EmitterPattern) -> Emitter {
+        Emitter::new(4.0, 0.0, VehicleProfile::Primary, Team::default(), pattern)
+    }
+
+    #[test]
+    fn continuous_emits_at_the_rate() {
+        let mut emitter = emitter(EmitterPattern::Continuous);
+
+        let counts: Vec<usize> = (0..40).map(|_| emitter.advance(0.25)).collect();
+
+        assert!(counts.iter().all(|count| *count == 1));
+        assert_eq!(emitter.emitted, 40);
+    }
+
+    #[test]
+    fn waves_pause_between_waves() {
+        let mut emitter = emitter(EmitterPattern::Waves);
+
+        let wave: usize = (0..8).map(|_| emitter.advance(0.25)).sum();
+        let pause: usize = (0..15).map(|_| emitter.advance(0.25)).sum();
+
+        assert_eq!(wave, 7);
+        assert_eq!(pause, 0);
+    }
+
+    #[test]
+    fn bursts_release_a_period_at_once() {
+        let mut emitter = emitter(EmitterPattern::Bursts);
+
+        let before: usize = (0..5).map(|_| emitter.advance(0.5)).sum();
+
+        assert_eq!(before, 0);
+        assert_eq!(emitter.advance(0.5), 12);
+        assert_eq!(emitter.emitted, 12);
+    }
+}
@@ -0,0 +1,331 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use rand::prelude::*;
+
+use crate::{
+    actions::{Action, Actions},
+    clock::{simulating, SimClock},
+    cursor_world_position,
+    evolution::mutate,
+    formations::FormationMember,
+    hud::Hud,
+    rng::SimRng,
+    snapshot::SnapshotApp,
+    vehicle::{
+        steer, Acceleration, DespawnSystem, Despawned, Genome, Mass, SpawnVehicle, Steering,
+        SteeringForce, Team, Vehicle, VehicleProfile, VehicleSystem, Velocity, VEHICLE_MAX_SPEED,
+        VEHICLE_SIZE,
+    },
+    world::WALL_MARGIN,
+    MainCamera,
+};
+
+pub const ENERGY_START: f32 = 50.0;
+pub const ENERGY_MAX: f32 = 150.0;
+// Vehicles above this split off an offspring carrying `ENERGY_START`
+const ENERGY_REPRODUCE: f32 = 100.0;
+// Vehicles below this look for food, harder the hungrier they are
+const ENERGY_HUNGRY: f32 = 80.0;
+
+// Drain per simulation second
+const ENERGY_BASE_DRAIN: f32 = 0.5;
+// At full speed
+const ENERGY_SPEED_DRAIN: f32 = 2.0;
+// Per unit of acceleration
+const ENERGY_FORCE_DRAIN: f32 = 0.1;
+
+const FOOD_ENERGY: f32 = 30.0;
+const FOOD_MAX: usize = 60;
+// Simulation seconds between food growing at a random spot
+const FOOD_INTERVAL: f32 = 0.5;
+const FOOD_RADIUS: f32 = 4.0;
+const FOOD_EAT_DIST: f32 = FOOD_RADIUS + VEHICLE_SIZE;
+const FOOD_PERCEPTION: f32 = VEHICLE_SIZE * 40.0;
+const FOOD_COLOR: Color = Color::LIME_GREEN;
+const FOOD_Z: f32 = 6.0;
+
+const FORAGE_FACTOR: f32 = 1.5;
+
+pub struct EnergyPlugin;
+
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Energy(pub f32);
+
+impl Energy {
+    pub fn gain(&mut self, amount: f32) {
+        self.0 = (self.0 + amount).min(ENERGY_MAX);
+    }
+}
+
+#[derive(Component, Reflect, FromReflect, Clone)]
+pub struct Food(pub f32);
+
+#[derive(Resource, Reflect, Default, Clone)]
+pub struct Lifecycle {
+    pub enabled: bool,
+    pub births: usize,
+    pub deaths: usize,
+    growth: f32,
+}
+
+impl Plugin for EnergyPlugin {
+    fn build(&self, app: &mut App) {
+        app.snapshot_component::<Energy>()
+            .init_resource::<Lifecycle>()
+            .add_system(lifecycle_controls)
+            .add_system(lifecycle_status)
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(simulating)
+                    .with_system(grow_food)
+                    .with_system(
+                        forage
+                            .label(VehicleSystem::Steer)
+                            .after(VehicleSystem::Flock),
+                    )
+                    .with_system(
+                        metabolize
+                            .after(VehicleSystem::Steer)
+                            .before(VehicleSystem::Move),
+                    ),
+            )
+            // Despawned after the update stage, like the vehicles caught by sinks
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                starve
+                    .with_run_criteria(simulating)
+                    .label(DespawnSystem::Starve)
+                    .after(DespawnSystem::Capture),
+            );
+    }
+}
+
+pub fn spawn_food(commands: &mut Commands, position: Vec2) -> Entity {
+    commands
+        .spawn(GeometryBuilder::build_as(
+            &shapes::Circle {
+                radius: FOOD_RADIUS,
+                ..shapes::Circle::default()
+            },
+            DrawMode::Fill(FillMode::color(FOOD_COLOR)),
+            Transform::from_translation(position.extend(FOOD_Z)),
+        ))
+        .insert(Name::new("Food"))
+        .insert(Food(FOOD_ENERGY))
+        .id()
+}
+
+fn lifecycle_controls(
+    mut commands: Commands,
+    mut lifecycle: ResMut<Lifecycle>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    windows: Res<Windows>,
+    actions: Res<Actions>,
+) {
+    if actions.just_pressed(Action::Lifecycle) {
+        lifecycle.enabled = !lifecycle.enabled;
+    }
+
+    if actions.just_pressed(Action::PlaceFood) {
+        let window = windows.get_primary().unwrap();
+        let (camera, camera_transform) = camera_query.single();
+
+        if let Some(world_pos) = cursor_world_position(window, camera, camera_transform) {
+            spawn_food(&mut commands, world_pos);
+        }
+    }
+}
+
+fn grow_food(
+    mut commands: Commands,
+    mut lifecycle: ResMut<Lifecycle>,
+    mut rng: ResMut<SimRng>,
+    food_query: Query<&Food>,
+    windows: Res<Windows>,
+    clock: Res<SimClock>,
+) {
+    if !lifecycle.enabled {
+        return;
+    }
+
+    let window = windows.get_primary().unwrap();
+    let bounds = Vec2::new(window.width(), window.height()) / 2.0 - WALL_MARGIN;
+
+    // No room inside the walls of a window this small
+    if bounds.x <= 0.0 || bounds.y <= 0.0 {
+        return;
+    }
+
+    lifecycle.growth += clock.delta_seconds();
+
+    let mut count = food_query.iter().len();
+
+    while lifecycle.growth >= FOOD_INTERVAL {
+        lifecycle.growth -= FOOD_INTERVAL;
+
+        if count < FOOD_MAX {
+            let x = rng.gen_range(-bounds.x..bounds.x);
+            let y = rng.gen_range(-bounds.y..bounds.y);
+            spawn_food(&mut commands, Vec2::new(x, y));
+            count += 1;
+        }
+    }
+}
+
+// Hungry vehicles seek the closest food they see, predators live off their prey instead
+fn forage(
+    mut vehicle_query: Query<
+        (
+            &Transform,
+            &Velocity,
+            &Energy,
+            &VehicleProfile,
+            &mut Acceleration,
+            &Mass,
+            &mut Steering,
+        ),
+        (With<Vehicle>, Without<FormationMember>),
+    >,
+    food_query: Query<&Transform, With<Food>>,
+    lifecycle: Res<Lifecycle>,
+) {
+    if !lifecycle.enabled || food_query.is_empty() {
+        return;
+    }
+
+    vehicle_query.for_each_mut(
+        |(transform, velocity, energy, profile, mut acceleration, mass, mut steering)| {
+            if *profile == VehicleProfile::Predator || energy.0 >= ENERGY_HUNGRY {
+                return;
+            }
+
+            let position = transform.translation.truncate();
+
+            let nearest = food_query
+                .iter()
+                .map(|food_transform| food_transform.translation.truncate())
+                .filter(|food| food.distance(position) <= FOOD_PERCEPTION)
+                .min_by(|a, b| {
+                    a.distance_squared(position)
+                        .total_cmp(&b.distance_squared(position))
+                });
+
+            if let Some(food) = nearest {
+                let desired = (food - position).normalize_or_zero() * VEHICLE_MAX_SPEED;
+                let hunger = 1.0 - energy.0 / ENERGY_HUNGRY;
+
+                let force = steer(desired, velocity.0) * FORAGE_FACTOR * hunger;
+                steering.record(SteeringForce::Forage, force);
+                acceleration.apply_force(force, mass);
+            }
+        },
+    );
+}
+
+// Drains energy for moving and steering, eats food in reach and splits off offspring
+fn metabolize(
+    mut commands: Commands,
+    mut lifecycle: ResMut<Lifecycle>,
+    mut rng: ResMut<SimRng>,
+    mut spawn_events: EventWriter<SpawnVehicle>,
+    mut vehicle_query: Query<
+        (
+            &Transform,
+            &Velocity,
+            &Acceleration,
+            &VehicleProfile,
+            &Team,
+            &Genome,
+            &mut Energy,
+        ),
+        With<Vehicle>,
+    >,
+    food_query: Query<(Entity, &Transform, &Food)>,
+    clock: Res<SimClock>,
+) {
+    if !lifecycle.enabled {
+        return;
+    }
+
+    let mut food: Vec<(Entity, Vec2, f32)> = food_query
+        .iter()
+        .map(|(entity, transform, food)| (entity, transform.translation.truncate(), food.0))
+        .collect();
+
+    for (transform, velocity, acceleration, profile, team, genome, mut energy) in
+        vehicle_query.iter_mut()
+    {
+        let position = transform.translation.truncate();
+
+        energy.0 -= (ENERGY_BASE_DRAIN
+            + velocity.length() / VEHICLE_MAX_SPEED * ENERGY_SPEED_DRAIN
+            + acceleration.length() * ENERGY_FORCE_DRAIN)
+            * clock.delta_seconds();
+
+        if *profile != VehicleProfile::Predator {
+            if let Some(index) = food
+                .iter()
+                .position(|(_, food, _)| food.distance(position) <= FOOD_EAT_DIST)
+            {
+                let (entity, _, amount) = food.swap_remove(index);
+                energy.gain(amount);
+                commands.entity(entity).despawn();
+            }
+        }
+
+        if energy.0 >= ENERGY_REPRODUCE {
+            energy.0 -= ENERGY_START;
+            lifecycle.births += 1;
+
+            // Dropped just behind the parent, heading the same way and carrying a mutated genome
+            spawn_events.send(SpawnVehicle {
+                position: position - velocity.normalize_or_zero() * VEHICLE_SIZE * 3.0,
+                velocity: velocity.0,
+                profile: *profile,
+                team: *team,
+                genome: mutate(genome, &mut rng),
+            });
+        }
+    }
+}
+
+fn starve(
+    mut commands: Commands,
+    mut lifecycle: ResMut<Lifecycle>,
+    mut despawned: ResMut<Despawned>,
+    vehicle_query: Query<(Entity, &Energy), With<Vehicle>>,
+) {
+    if !lifecycle.enabled {
+        return;
+    }
+
+    for (entity, energy) in vehicle_query.iter() {
+        if energy.0 <= 0.0 && despawned.take(&mut commands, entity) {
+            lifecycle.deaths += 1;
+        }
+    }
+}
+
+fn lifecycle_status(
+    mut hud: ResMut<Hud>,
+    lifecycle: Res<Lifecycle>,
+    vehicle_query: Query<(), With<Vehicle>>,
+    food_query: Query<(), With<Food>>,
+) {
+    if !lifecycle.enabled {
+        hud.clear("lifecycle");
+        return;
+    }
+
+    hud.set(
+        "lifecycle",
+        format!(
+            "Alive {}, born {}, starved {}, food {}",
+            vehicle_query.iter().len(),
+            lifecycle.births,
+            lifecycle.deaths,
+            food_query.iter().len()
+        ),
+    );
+}
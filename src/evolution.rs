@@ -0,0 +1,222 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use rand::prelude::*;
+
+use crate::{
+    actions::{Action, Actions},
+    rng::SimRng,
+    vehicle::{Genome, Vehicle, VEHICLE_SIZE},
+};
+
+// Largest relative change of a gene between parent and offspring
+const MUTATION_SCALE: f32 = 0.15;
+// Largest absolute change on top, relative to the range of the gene, so genes at zero
+// can still grow back
+const MUTATION_NUDGE: f32 = 0.01;
+const FACTOR_MAX: f32 = 5.0;
+const DIST_MIN: f32 = VEHICLE_SIZE;
+const DIST_MAX: f32 = VEHICLE_SIZE * 40.0;
+
+const GENE_NAMES: [&str; 9] = [
+    "Separation",
+    "Alignment",
+    "Cohesion",
+    "View",
+    "Wander",
+    "Separation dist",
+    "Align dist",
+    "Cohesion dist",
+    "View dist",
+];
+
+pub struct EvolutionPlugin;
+
+#[derive(Resource, Default)]
+struct Population {
+    open: bool,
+}
+
+impl Plugin for EvolutionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Population>()
+            .add_system(toggle_population)
+            .add_system(draw_population.after(toggle_population));
+    }
+}
+
+fn genes(genome: &Genome) -> [f32; 9] {
+    [
+        genome.separation,
+        genome.alignment,
+        genome.cohesion,
+        genome.view,
+        genome.wander,
+        genome.separation_dist,
+        genome.align_dist,
+        genome.cohesion_dist,
+        genome.view_dist,
+    ]
+}
+
+fn mutate_gene(gene: &mut f32, rng: &mut SimRng, min: f32, max: f32) {
+    let scale = 1.0 + rng.gen_range(-MUTATION_SCALE..MUTATION_SCALE);
+    let nudge = rng.gen_range(-MUTATION_NUDGE..MUTATION_NUDGE) * (max - min);
+
+    *gene = (*gene * scale + nudge).clamp(min, max);
+}
+
+// Genome of an offspring, one generation after `parent`
+pub fn mutate(parent: &Genome, rng: &mut SimRng) -> Genome {
+    let mut genome = *parent;
+
+    for factor in [
+        &mut genome.separation,
+        &mut genome.alignment,
+        &mut genome.cohesion,
+        &mut genome.view,
+        &mut genome.wander,
+    ] {
+        mutate_gene(factor, rng, 0.0, FACTOR_MAX);
+    }
+
+    for dist in [
+        &mut genome.separation_dist,
+        &mut genome.align_dist,
+        &mut genome.cohesion_dist,
+        &mut genome.view_dist,
+    ] {
+        mutate_gene(dist, rng, DIST_MIN, DIST_MAX);
+    }
+
+    genome.generation += 1;
+    genome
+}
+
+fn toggle_population(mut population: ResMut<Population>, actions: Res<Actions>) {
+    if actions.just_pressed(Action::Population) {
+        population.open = !population.open;
+    }
+}
+
+// Spread of every gene over the living vehicles, next to the value they started with
+fn draw_population(
+    mut egui_context: ResMut<EguiContext>,
+    mut population: ResMut<Population>,
+    genome_query: Query<&Genome, With<Vehicle>>,
+) {
+    if !population.open {
+        return;
+    }
+
+    let genomes: Vec<[f32; 9]> = genome_query.iter().map(genes).collect();
+    let generations: Vec<u32> = genome_query
+        .iter()
+        .map(|genome| genome.generation)
+        .collect();
+    let count = genomes.len().max(1) as f32;
+
+    let mut open = true;
+
+    egui::Window::new("Population")
+        .open(&mut open)
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.label(format!(
+                "Vehicles {}, generation mean {:.1}, max {}",
+                genomes.len(),
+                generations.iter().sum::<u32>() as f32 / count,
+                generations.iter().max().copied().unwrap_or(0)
+            ));
+
+            egui::Grid::new("genes").striped(true).show(ui, |ui| {
+                for heading in ["Gene", "Start", "Mean", "Std dev", "Min", "Max"] {
+                    ui.label(heading);
+                }
+                ui.end_row();
+
+                let start = genes(&Genome::default());
+
+                for (index, name) in GENE_NAMES.iter().enumerate() {
+                    let values = genomes.iter().map(|genes| genes[index]);
+
+                    let mean = values.clone().sum::<f32>() / count;
+                    let variance = values.clone().map(|v| (v - mean).powi(2)).sum::<f32>() / count;
+                    let min = values.clone().fold(f32::INFINITY, f32::min);
+                    let max = values.fold(f32::NEG_INFINITY, f32::max);
+
+                    ui.label(*name);
+                    ui.label(format!("{:.2}", start[index]));
+                    ui.label(format!("{:.2}", mean));
+                    ui.label(format!("{:.2}", variance.sqrt()));
+
+                    if genomes.is_empty() {
+                        ui.label("-");
+                        ui.label("-");
+                    } else {
+                        ui.label(format!("{:.2}", min));
+                        ui.label(format!("{:.2}", max));
+                    }
+
+                    ui.end_row();
+                }
+            });
+        });
+
+    population.open = open;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rng() -> SimRng {
+        let mut rng = SimRng::default();
+        rng.seed(11);
+        rng
+    }
+
+    #[test]
+    fn offspring_stays_within_the_gene_ranges() {
+        let mut rng = rng();
+        let mut genome = Genome::default();
+
+        for generation in 1..=200 {
+            genome = mutate(&genome, &mut rng);
+            assert_eq!(genome.generation, generation);
+
+            let genes = genes(&genome);
+            assert!(genes[..5]
+                .iter()
+                .all(|gene| (0.0..=FACTOR_MAX).contains(gene)));
+            assert!(genes[5..]
+                .iter()
+                .all(|gene| (DIST_MIN..=DIST_MAX).contains(gene)));
+        }
+    }
+
+    #[test]
+    fn mutation_changes_genes_by_a_bounded_amount() {
+        let mut rng = rng();
+        let parent = Genome::default();
+        let child = mutate(&parent, &mut rng);
+
+        for (parent, child) in genes(&parent).iter().zip(genes(&child).iter()) {
+            assert!((child - parent).abs() <= parent * MUTATION_SCALE + MUTATION_NUDGE * DIST_MAX);
+        }
+    }
+
+    #[test]
+    fn genes_at_zero_can_grow_back() {
+        let mut rng = rng();
+        let mut genome = Genome {
+            cohesion: 0.0,
+            ..Genome::default()
+        };
+
+        let grew = (0..20).any(|_| {
+            genome = mutate(&genome, &mut rng);
+            genome.cohesion > 0.0
+        });
+
+        assert!(grew);
+    }
+}
@@ -0,0 +1,541 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+
+use crate::{
+    actions::{Action, Actions},
+    clock::{simulating, SimClock},
+    cursor_world_position,
+    hud::Hud,
+    target::Target,
+    vehicle::{
+        steer, Acceleration, Mass, Steering, SteeringForce, Vehicle, VehicleSystem, Velocity,
+        VEHICLE_MAX_SPEED, VEHICLE_SIZE,
+    },
+    world::WALL_MARGIN,
+    MainCamera,
+};
+
+// Vehicles this close to the cursor join a new formation
+const FORMATION_GATHER_RADIUS: f32 = 120.0;
+const FORMATION_SPACING: f32 = VEHICLE_SIZE * 6.0;
+const FORMATION_SPEED: f32 = 120.0;
+// The anchor waits while its members are on average further than this from their slots
+const FORMATION_WAIT_DIST: f32 = FORMATION_SPACING * 2.0;
+// Members slow down within this distance of their slot
+const FORMATION_ARRIVE_RADIUS: f32 = FORMATION_SPACING * 2.0;
+// The anchor stops this close to the target
+const FORMATION_STOP_DIST: f32 = 10.0;
+const FORMATION_FACTOR: f32 = 2.5;
+
+const ANCHOR_SIZE: f32 = 10.0;
+const ANCHOR_COLOR: Color = Color::YELLOW;
+const ANCHOR_LINE_WIDTH: f32 = 2.0;
+const ANCHOR_Z: f32 = 8.0;
+
+pub struct FormationPlugin;
+
+#[derive(Reflect, FromReflect, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FormationShape {
+    // Side by side
+    Line,
+    Wedge,
+    // One behind the other
+    Column,
+    Circle,
+    Grid,
+}
+
+impl FormationShape {
+    fn next(&self) -> Self {
+        match self {
+            FormationShape::Line => FormationShape::Wedge,
+            FormationShape::Wedge => FormationShape::Column,
+            FormationShape::Column => FormationShape::Circle,
+            FormationShape::Circle => FormationShape::Grid,
+            FormationShape::Grid => FormationShape::Line,
+        }
+    }
+
+    // Slot positions relative to the anchor, x pointing forward and y to the left
+    fn slots(&self, count: usize) -> Vec<Vec2> {
+        let center = (count as f32 - 1.0) / 2.0;
+        let columns = (count as f32).sqrt().ceil() as usize;
+        let radius = (FORMATION_SPACING * count as f32 / (2.0 * PI)).max(FORMATION_SPACING);
+
+        (0..count)
+            .map(|index| match self {
+                FormationShape::Line => Vec2::new(0.0, (index as f32 - center) * FORMATION_SPACING),
+                FormationShape::Wedge => {
+                    let rank = ((index + 1) / 2) as f32;
+                    let side = if index % 2 == 1 { 1.0 } else { -1.0 };
+                    Vec2::new(-rank, side * rank) * FORMATION_SPACING
+                }
+                FormationShape::Column => Vec2::new(-(index as f32) * FORMATION_SPACING, 0.0),
+                FormationShape::Circle => {
+                    let angle = index as f32 / count as f32 * 2.0 * PI;
+                    Vec2::new(angle.cos(), angle.sin()) * radius
+                }
+                FormationShape::Grid => {
+                    let row = (index / columns) as f32;
+                    let column = (index % columns) as f32 - (columns as f32 - 1.0) / 2.0;
+                    Vec2::new(-row, column) * FORMATION_SPACING
+                }
+            })
+            .collect()
+    }
+}
+
+// Anchor of a group of vehicles, each member holds the slot at its index
+#[derive(Component, Reflect, FromReflect, Clone)]
+pub struct Formation {
+    pub shape: FormationShape,
+    // Saved by snapshots separately, entities do not outlive a reload
+    #[reflect(ignore)]
+    pub members: Vec<Entity>,
+    heading: Vec2,
+    velocity: Vec2,
+}
+
+impl Formation {
+    // Members hold no wall forces, so slots past a wall are pulled back inside `bounds`
+    fn slot_positions(&self, anchor: Vec2, bounds: Vec2) -> Vec<Vec2> {
+        self.shape
+            .slots(self.members.len())
+            .into_iter()
+            .map(|slot| {
+                (anchor + self.heading * slot.x + self.heading.perp() * slot.y)
+                    .clamp(-bounds, bounds)
+            })
+            .collect()
+    }
+
+    // Hands each slot, front to back, to the closest member that has none yet
+    fn assign_slots(
+        &mut self,
+        anchor: Vec2,
+        bounds: Vec2,
+        position: impl Fn(Entity) -> Option<Vec2>,
+    ) {
+        let mut unassigned: Vec<(Entity, Vec2)> = self
+            .members
+            .iter()
+            .filter_map(|member| position(*member).map(|position| (*member, position)))
+            .collect();
+
+        // Lost members get no slot
+        self.members = unassigned.iter().map(|(member, _)| *member).collect();
+
+        self.members = self
+            .slot_positions(anchor, bounds)
+            .into_iter()
+            .map(|slot| {
+                let closest = unassigned
+                    .iter()
+                    .enumerate()
+                    .min_by(|(_, (_, a)), (_, (_, b))| {
+                        a.distance_squared(slot)
+                            .total_cmp(&b.distance_squared(slot))
+                    })
+                    .map(|(index, _)| index)
+                    .unwrap();
+                unassigned.swap_remove(closest).0
+            })
+            .collect();
+    }
+}
+
+// Vehicles in a formation hold their slot, no other steering force acts on them
+#[derive(Component)]
+pub struct FormationMember;
+
+// Shape new formations are given
+#[derive(Resource)]
+struct FormationSettings {
+    shape: FormationShape,
+    // Whether formations were used yet, the status stays hidden until then
+    used: bool,
+}
+
+impl Default for FormationSettings {
+    fn default() -> Self {
+        Self {
+            shape: FormationShape::Wedge,
+            used: false,
+        }
+    }
+}
+
+impl Plugin for FormationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FormationSettings>()
+            .add_system(formation_controls)
+            .add_system(formation_status)
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(simulating)
+                    .with_system(move_anchors.before(VehicleSystem::Flock))
+                    .with_system(
+                        hold_formation
+                            .label(VehicleSystem::Flock)
+                            .label(VehicleSystem::Steer),
+                    ),
+            );
+    }
+}
+
+fn world_bounds(windows: &Windows) -> Vec2 {
+    let window = windows.get_primary().unwrap();
+    Vec2::new(window.width(), window.height()) / 2.0 - WALL_MARGIN
+}
+
+pub fn spawn_formation(commands: &mut Commands, position: Vec2, formation: Formation) -> Entity {
+    let diamond = shapes::Polygon {
+        points: vec![
+            Vec2::new(ANCHOR_SIZE, 0.0),
+            Vec2::new(0.0, ANCHOR_SIZE / 2.0),
+            Vec2::new(-ANCHOR_SIZE / 2.0, 0.0),
+            Vec2::new(0.0, -ANCHOR_SIZE / 2.0),
+        ],
+        closed: true,
+    };
+
+    commands
+        .spawn(GeometryBuilder::build_as(
+            &diamond,
+            DrawMode::Stroke(StrokeMode::new(ANCHOR_COLOR, ANCHOR_LINE_WIDTH)),
+            Transform {
+                translation: position.extend(ANCHOR_Z),
+                rotation: Quat::from_rotation_z(formation.heading.y.atan2(formation.heading.x)),
+                ..Default::default()
+            },
+        ))
+        .insert(Name::new("Formation"))
+        .insert(formation)
+        .id()
+}
+
+// Gathers the free vehicles around the cursor into a formation facing the target, or
+// disbands the formation whose anchor is under the cursor
+#[allow(clippy::too_many_arguments)]
+fn formation_controls(
+    mut commands: Commands,
+    mut settings: ResMut<FormationSettings>,
+    mut formation_query: Query<(Entity, &Transform, &mut Formation), Without<Vehicle>>,
+    vehicle_query: Query<(Entity, &Transform, Option<&FormationMember>), With<Vehicle>>,
+    target_query: Query<&Transform, (With<Target>, Without<Formation>)>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    windows: Res<Windows>,
+    actions: Res<Actions>,
+) {
+    let shape_changed = actions.just_pressed(Action::FormationShape);
+    if shape_changed {
+        settings.shape = settings.shape.next();
+    }
+
+    if !shape_changed && !actions.just_pressed(Action::Formation) {
+        return;
+    }
+
+    settings.used = true;
+
+    let window = windows.get_primary().unwrap();
+    let (camera, camera_transform) = camera_query.single();
+
+    let world_pos = match cursor_world_position(window, camera, camera_transform) {
+        Some(world_pos) => world_pos,
+        None => return,
+    };
+
+    let position = |entity: Entity| {
+        vehicle_query
+            .get(entity)
+            .ok()
+            .map(|(_, transform, _)| transform.translation.truncate())
+    };
+
+    let hovered = formation_query.iter_mut().find(|(_, transform, _)| {
+        transform.translation.truncate().distance(world_pos) <= ANCHOR_SIZE * 2.0
+    });
+
+    if let Some((entity, transform, mut formation)) = hovered {
+        if shape_changed {
+            formation.shape = settings.shape;
+            formation.assign_slots(
+                transform.translation.truncate(),
+                world_bounds(&windows),
+                position,
+            );
+        } else {
+            // Members lost while the simulation was paused are still listed
+            for member in formation.members.iter() {
+                if let Some(mut member) = commands.get_entity(*member) {
+                    member.remove::<FormationMember>();
+                }
+            }
+            commands.entity(entity).despawn();
+        }
+        return;
+    }
+
+    if shape_changed {
+        return;
+    }
+
+    let members: Vec<(Entity, Vec2)> = vehicle_query
+        .iter()
+        .filter(|(_, _, member)| member.is_none())
+        .map(|(entity, transform, _)| (entity, transform.translation.truncate()))
+        .filter(|(_, position)| position.distance(world_pos) <= FORMATION_GATHER_RADIUS)
+        .collect();
+
+    if members.len() < 2 {
+        return;
+    }
+
+    let anchor = members.iter().map(|(_, position)| *position).sum::<Vec2>() / members.len() as f32;
+
+    let heading = target_query
+        .get_single()
+        .map(|transform| (transform.translation.truncate() - anchor).normalize_or_zero())
+        .ok()
+        .filter(|heading| *heading != Vec2::ZERO)
+        .unwrap_or(Vec2::X);
+
+    let mut formation = Formation {
+        shape: settings.shape,
+        members: members.iter().map(|(entity, _)| *entity).collect(),
+        heading,
+        velocity: Vec2::ZERO,
+    };
+    formation.assign_slots(anchor, world_bounds(&windows), position);
+
+    for (entity, _) in members.iter() {
+        commands.entity(*entity).insert(FormationMember);
+    }
+
+    spawn_formation(&mut commands, anchor, formation);
+}
+
+// Drops lost members, handing their slots over, and moves the anchors towards the target
+// at a pace the members can keep up with
+fn move_anchors(
+    mut commands: Commands,
+    mut formation_query: Query<(Entity, &mut Transform, &mut Formation), Without<Vehicle>>,
+    member_query: Query<&Transform, (With<Vehicle>, With<FormationMember>)>,
+    target_query: Query<&Transform, (With<Target>, Without<Formation>)>,
+    windows: Res<Windows>,
+    clock: Res<SimClock>,
+) {
+    let bounds = world_bounds(&windows);
+
+    let target = target_query
+        .get_single()
+        .ok()
+        .map(|transform| transform.translation.truncate());
+
+    let position = |entity: Entity| {
+        member_query
+            .get(entity)
+            .ok()
+            .map(|transform| transform.translation.truncate())
+    };
+
+    for (entity, mut transform, mut formation) in formation_query.iter_mut() {
+        let anchor = transform.translation.truncate();
+
+        let count = formation.members.len();
+        formation
+            .members
+            .retain(|member| position(*member).is_some());
+
+        if formation.members.is_empty() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        if formation.members.len() != count {
+            formation.assign_slots(anchor, bounds, position);
+        }
+
+        let lag = formation
+            .slot_positions(anchor, bounds)
+            .iter()
+            .zip(formation.members.iter())
+            .filter_map(|(slot, member)| position(*member).map(|position| position.distance(*slot)))
+            .sum::<f32>()
+            / formation.members.len() as f32;
+
+        formation.velocity = Vec2::ZERO;
+
+        if let Some(target) = target {
+            let offset = target - anchor;
+            let dist = offset.length();
+
+            if dist > FORMATION_STOP_DIST {
+                formation.heading = offset / dist;
+
+                if lag <= FORMATION_WAIT_DIST {
+                    let step = (FORMATION_SPEED * clock.delta_seconds()).min(dist);
+                    formation.velocity = formation.heading * FORMATION_SPEED;
+                    transform.translation += (formation.heading * step).extend(0.0);
+                }
+            }
+        }
+
+        transform.rotation = Quat::from_rotation_z(formation.heading.y.atan2(formation.heading.x));
+    }
+}
+
+// Members arrive at their slot while keeping pace with the anchor
+fn hold_formation(
+    formation_query: Query<(&Transform, &Formation), Without<Vehicle>>,
+    mut member_query: Query<
+        (
+            &Transform,
+            &Velocity,
+            &mut Acceleration,
+            &Mass,
+            &mut Steering,
+        ),
+        (With<Vehicle>, With<FormationMember>),
+    >,
+    windows: Res<Windows>,
+) {
+    let bounds = world_bounds(&windows);
+
+    for (transform, formation) in formation_query.iter() {
+        let slots = formation.slot_positions(transform.translation.truncate(), bounds);
+
+        for (slot, member) in slots.iter().zip(formation.members.iter()) {
+            if let Ok((member_transform, velocity, mut acceleration, mass, mut steering)) =
+                member_query.get_mut(*member)
+            {
+                *steering = Steering::default();
+
+                let offset = *slot - member_transform.translation.truncate();
+                let speed =
+                    VEHICLE_MAX_SPEED * (offset.length() / FORMATION_ARRIVE_RADIUS).min(1.0);
+                let desired = offset.normalize_or_zero() * speed + formation.velocity;
+
+                let force = steer(desired, velocity.0) * FORMATION_FACTOR;
+                steering.record(SteeringForce::Formation, force);
+                acceleration.apply_force(force, mass);
+            }
+        }
+    }
+}
+
+fn formation_status(
+    mut hud: ResMut<Hud>,
+    settings: Res<FormationSettings>,
+    formation_query: Query<&Formation>,
+) {
+    if !settings.used {
+        hud.clear("formations");
+        return;
+    }
+
+    let formations: Vec<String> = formation_query
+        .iter()
+        .map(|formation| format!("{:?} {}", formation.shape, formation.members.len()))
+        .collect();
+
+    hud.set(
+        "formations",
+        format!(
+            "Formation {:?}, formations {}",
+            settings.shape,
+            if formations.is_empty() {
+                "none".to_string()
+            } else {
+                formations.join(", ")
+            }
+        ),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHAPES: [FormationShape; 5] = [
+        FormationShape::Line,
+        FormationShape::Wedge,
+        FormationShape::Column,
+        FormationShape::Circle,
+        FormationShape::Grid,
+    ];
+
+    fn formation(shape: FormationShape, count: u32) -> Formation {
+        Formation {
+            shape,
+            members: (0..count).map(Entity::from_raw).collect(),
+            heading: Vec2::X,
+            velocity: Vec2::ZERO,
+        }
+    }
+
+    #[test]
+    fn every_member_gets_its_own_slot() {
+        for shape in SHAPES {
+            for count in [1, 2, 7, 16] {
+                let slots = shape.slots(count);
+                assert_eq!(slots.len(), count);
+
+                for (i, a) in slots.iter().enumerate() {
+                    for b in slots[i + 1..].iter() {
+                        assert!(a.distance(*b) > FORMATION_SPACING / 2.0, "{:?}", shape);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn line_is_centered_on_the_anchor() {
+        let sum: Vec2 = FormationShape::Line.slots(5).into_iter().sum();
+        assert!(sum.length() < 1e-3);
+    }
+
+    #[test]
+    fn slots_stay_inside_the_bounds() {
+        let bounds = Vec2::splat(FORMATION_SPACING);
+        let formation = formation(FormationShape::Line, 9);
+
+        assert!(formation
+            .slot_positions(Vec2::ZERO, bounds)
+            .iter()
+            .all(|slot| slot.abs().cmple(bounds).all()));
+    }
+
+    #[test]
+    fn members_take_the_closest_slot() {
+        let mut formation = formation(FormationShape::Line, 3);
+        let bounds = Vec2::splat(1000.0);
+        let slots = formation.slot_positions(Vec2::ZERO, bounds);
+
+        // Every member already stands on a slot, just not the one at its index
+        formation.assign_slots(Vec2::ZERO, bounds, |member| {
+            Some(slots[(member.index() as usize + 1) % 3])
+        });
+
+        for (slot, member) in formation.members.iter().enumerate() {
+            assert_eq!((member.index() as usize + 1) % 3, slot);
+        }
+    }
+
+    #[test]
+    fn lost_members_are_dropped() {
+        let mut formation = formation(FormationShape::Column, 4);
+        let lost = Entity::from_raw(2);
+
+        formation.assign_slots(Vec2::ZERO, Vec2::splat(1000.0), |member| {
+            (member != lost).then_some(Vec2::ZERO)
+        });
+
+        assert_eq!(formation.members.len(), 3);
+        assert!(!formation.members.contains(&lost));
+    }
+}
@@ -0,0 +1,71 @@
+use bevy::prelude::*;
+
+use crate::{
+    actions::{Action, Actions},
+    target::{Target, TargetMode, TargetMotion, TargetSystem},
+    vehicle::VehicleSystem,
+    world::WALL_MARGIN,
+};
+
+// Target speed with the stick fully tilted
+const GAMEPAD_TARGET_SPEED: f32 = 600.0;
+// Stick tilt that takes over the target from the cursor
+const GAMEPAD_TAKEOVER: f32 = 0.3;
+
+pub struct GamepadPlugin;
+
+impl Plugin for GamepadPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            gamepad_target
+                .after(TargetSystem::Move)
+                .before(VehicleSystem::Steer),
+        );
+    }
+}
+
+fn gamepad_target(
+    mut motion: ResMut<TargetMotion>,
+    mut target_query: Query<(&mut Transform, &mut Target)>,
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    windows: Res<Windows>,
+    actions: Res<Actions>,
+    time: Res<Time>,
+) {
+    let (mut transform, mut target) = target_query.single_mut();
+
+    let window = windows.get_primary().unwrap();
+    let bounds = Vec2::new(window.width(), window.height()) / 2.0 - WALL_MARGIN;
+
+    let mut stick = Vec2::ZERO;
+
+    for gamepad in gamepads.iter() {
+        let axis = |axis_type| {
+            axes.get(GamepadAxis::new(gamepad, axis_type))
+                .unwrap_or(0.0)
+        };
+
+        stick += Vec2::new(
+            axis(GamepadAxisType::LeftStickX),
+            axis(GamepadAxisType::LeftStickY),
+        );
+    }
+
+    // Picking up the gamepad while the target follows the cursor hands it over
+    if motion.mode == TargetMode::Cursor && stick.length() > GAMEPAD_TAKEOVER {
+        motion.mode = TargetMode::Gamepad;
+    }
+
+    if motion.mode != TargetMode::Gamepad {
+        return;
+    }
+
+    let position = (transform.translation.truncate()
+        + stick.clamp_length_max(1.0) * GAMEPAD_TARGET_SPEED * time.delta_seconds())
+    .clamp(-bounds, bounds);
+
+    transform.translation.x = position.x;
+    transform.translation.y = position.y;
+    target.engaged = actions.pressed(Action::Attract);
+}
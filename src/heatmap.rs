@@ -0,0 +1,342 @@
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    window::WindowResized,
+};
+
+use crate::{
+    actions::{Action, Actions},
+    clock::{simulating, SimClock},
+    hud::Hud,
+    vehicle::{Vehicle, VehicleSystem},
+};
+
+const HEATMAP_CELL_SIZE: f32 = 8.0;
+const HEATMAP_ALPHA: f32 = 0.7;
+const HEATMAP_Z: f32 = 5.0;
+const HEATMAP_EXPORT_PATH: &str = "heatmap.png";
+
+// Half-lives of the visitation layer in simulation seconds, `None` never forgets
+const HEATMAP_HALF_LIVES: [Option<f32>; 6] = [
+    None,
+    Some(120.0),
+    Some(60.0),
+    Some(30.0),
+    Some(10.0),
+    Some(3.0),
+];
+
+pub struct HeatmapPlugin;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeatmapLayer {
+    // Accumulated time spent in each cell
+    Visitation,
+    // Vehicles in each cell right now
+    Density,
+}
+
+#[derive(Resource)]
+pub struct HeatmapSettings {
+    pub visible: bool,
+    pub layer: HeatmapLayer,
+    half_life: usize,
+}
+
+impl Default for HeatmapSettings {
+    fn default() -> Self {
+        Self {
+            visible: false,
+            layer: HeatmapLayer::Visitation,
+            half_life: 0,
+        }
+    }
+}
+
+impl HeatmapSettings {
+    pub fn half_life(&self) -> Option<f32> {
+        HEATMAP_HALF_LIVES[self.half_life]
+    }
+}
+
+#[derive(Resource)]
+pub struct Heatmap {
+    size: UVec2,
+    // World position of the bottom left corner
+    origin: Vec2,
+    visitation: Vec<f32>,
+}
+
+impl Heatmap {
+    fn new(world_size: Vec2) -> Self {
+        let size = (world_size / HEATMAP_CELL_SIZE).ceil().as_uvec2().max(UVec2::ONE);
+
+        Self {
+            size,
+            origin: -size.as_vec2() * HEATMAP_CELL_SIZE / 2.0,
+            visitation: vec![0.0; (size.x * size.y) as usize],
+        }
+    }
+
+    fn world_size(&self) -> Vec2 {
+        self.size.as_vec2() * HEATMAP_CELL_SIZE
+    }
+
+    fn cell(&self, position: Vec2) -> Option<usize> {
+        let cell = ((position - self.origin) / HEATMAP_CELL_SIZE).floor();
+
+        if cell.x < 0.0 || cell.y < 0.0 {
+            return None;
+        }
+
+        let cell = cell.as_uvec2();
+        if cell.x >= self.size.x || cell.y >= self.size.y {
+            return None;
+        }
+
+        Some((cell.y * self.size.x + cell.x) as usize)
+    }
+
+    fn density<'a>(&self, positions: impl Iterator<Item = &'a Transform>) -> Vec<f32> {
+        let mut density = vec![0.0; self.visitation.len()];
+
+        for transform in positions {
+            if let Some(cell) = self.cell(transform.translation.truncate()) {
+                density[cell] += 1.0;
+            }
+        }
+
+        density
+    }
+
+    // RGBA pixels with the first row at the top, like the image expects
+    fn pixels(&self, values: &[f32]) -> Vec<u8> {
+        let max = values.iter().copied().fold(0.0, f32::max);
+        let mut pixels = Vec::with_capacity(values.len() * 4);
+
+        for row in values.chunks(self.size.x as usize).rev() {
+            for value in row {
+                let t = if max > 0.0 { (value / max).sqrt() } else { 0.0 };
+                pixels.extend_from_slice(&heat_color(t));
+            }
+        }
+
+        pixels
+    }
+
+    fn layer_image<'a>(
+        &self,
+        layer: HeatmapLayer,
+        positions: impl Iterator<Item = &'a Transform>,
+    ) -> Image {
+        match layer {
+            HeatmapLayer::Visitation => self.image(&self.visitation),
+            HeatmapLayer::Density => self.image(&self.density(positions)),
+        }
+    }
+
+    fn image(&self, values: &[f32]) -> Image {
+        Image::new(
+            Extent3d {
+                width: self.size.x,
+                height: self.size.y,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            self.pixels(values),
+            TextureFormat::Rgba8UnormSrgb,
+        )
+    }
+}
+
+// Black through red and yellow to white, transparent where nothing happened
+fn heat_color(t: f32) -> [u8; 4] {
+    let t = t.clamp(0.0, 1.0);
+    let r = (t * 3.0).min(1.0);
+    let g = (t * 3.0 - 1.0).clamp(0.0, 1.0);
+    let b = (t * 3.0 - 2.0).clamp(0.0, 1.0);
+    let a = (t * 3.0).min(1.0) * HEATMAP_ALPHA;
+
+    [
+        (r * 255.0) as u8,
+        (g * 255.0) as u8,
+        (b * 255.0) as u8,
+        (a * 255.0) as u8,
+    ]
+}
+
+#[derive(Component)]
+struct HeatmapOverlay;
+
+impl Plugin for HeatmapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HeatmapSettings>()
+            .add_startup_system(create_heatmap)
+            .add_system(resize_heatmap)
+            .add_system(
+                accumulate
+                    .with_run_criteria(simulating)
+                    .after(VehicleSystem::Move),
+            )
+            .add_system(heatmap_controls)
+            .add_system(draw_heatmap.after(accumulate).after(heatmap_controls));
+    }
+}
+
+fn create_heatmap(mut commands: Commands, mut images: ResMut<Assets<Image>>, windows: Res<Windows>) {
+    let window = windows.get_primary().unwrap();
+    let heatmap = Heatmap::new(Vec2::new(window.width(), window.height()));
+
+    commands
+        .spawn(SpriteBundle {
+            sprite: Sprite {
+                custom_size: Some(heatmap.world_size()),
+                ..default()
+            },
+            texture: images.add(heatmap.image(&heatmap.visitation)),
+            transform: Transform::from_xyz(0.0, 0.0, HEATMAP_Z),
+            visibility: Visibility { is_visible: false },
+            ..default()
+        })
+        .insert(HeatmapOverlay)
+        .insert(Name::new("Heatmap"));
+
+    commands.insert_resource(heatmap);
+}
+
+// The grid covers the window, so resizing starts a new one
+fn resize_heatmap(
+    mut heatmap: ResMut<Heatmap>,
+    mut events: EventReader<WindowResized>,
+    mut overlay_query: Query<&mut Sprite, With<HeatmapOverlay>>,
+) {
+    if let Some(event) = events.iter().last() {
+        *heatmap = Heatmap::new(Vec2::new(event.width, event.height));
+        overlay_query.single_mut().custom_size = Some(heatmap.world_size());
+    }
+}
+
+fn accumulate(
+    mut heatmap: ResMut<Heatmap>,
+    settings: Res<HeatmapSettings>,
+    clock: Res<SimClock>,
+    vehicle_query: Query<&Transform, With<Vehicle>>,
+) {
+    let delta = clock.delta_seconds();
+
+    if let Some(half_life) = settings.half_life() {
+        let decay = 0.5_f32.powf(delta / half_life);
+        heatmap.visitation.iter_mut().for_each(|value| *value *= decay);
+    }
+
+    for transform in vehicle_query.iter() {
+        if let Some(cell) = heatmap.cell(transform.translation.truncate()) {
+            heatmap.visitation[cell] += delta;
+        }
+    }
+}
+
+fn heatmap_controls(
+    mut settings: ResMut<HeatmapSettings>,
+    mut heatmap: ResMut<Heatmap>,
+    mut hud: ResMut<Hud>,
+    vehicle_query: Query<&Transform, With<Vehicle>>,
+    actions: Res<Actions>,
+) {
+    if actions.just_pressed(Action::HeatmapToggle) {
+        settings.visible = !settings.visible;
+    }
+
+    if actions.just_pressed(Action::HeatmapLayer) {
+        settings.layer = match settings.layer {
+            HeatmapLayer::Visitation => HeatmapLayer::Density,
+            HeatmapLayer::Density => HeatmapLayer::Visitation,
+        };
+    }
+
+    if actions.just_pressed(Action::HeatmapFasterDecay) {
+        settings.half_life = (settings.half_life + 1).min(HEATMAP_HALF_LIVES.len() - 1);
+    }
+
+    if actions.just_pressed(Action::HeatmapSlowerDecay) {
+        settings.half_life = settings.half_life.saturating_sub(1);
+    }
+
+    if actions.just_pressed(Action::HeatmapClear) {
+        heatmap.visitation.iter_mut().for_each(|value| *value = 0.0);
+    }
+
+    if actions.just_pressed(Action::HeatmapExport) {
+        match heatmap
+            .layer_image(settings.layer, vehicle_query.iter())
+            .try_into_dynamic()
+            .and_then(|image| Ok(image.save(HEATMAP_EXPORT_PATH)?))
+        {
+            Ok(_) => info!("Exported {:?} heatmap to {}", settings.layer, HEATMAP_EXPORT_PATH),
+            Err(err) => error!("Failed to export heatmap: {}", err),
+        }
+    }
+
+    if !settings.is_changed() {
+        return;
+    }
+
+    if settings.visible {
+        let half_life = match settings.half_life() {
+            Some(half_life) => format!("half-life {}s", half_life),
+            None => "no decay".to_string(),
+        };
+        hud.set("heatmap", format!("Heatmap {:?} ({})", settings.layer, half_life));
+    } else {
+        hud.clear("heatmap");
+    }
+}
+
+fn draw_heatmap(
+    heatmap: Res<Heatmap>,
+    settings: Res<HeatmapSettings>,
+    mut images: ResMut<Assets<Image>>,
+    mut overlay_query: Query<(&Handle<Image>, &mut Visibility), With<HeatmapOverlay>>,
+    vehicle_query: Query<&Transform, With<Vehicle>>,
+) {
+    let (handle, mut visibility) = overlay_query.single_mut();
+
+    if visibility.is_visible != settings.visible {
+        visibility.is_visible = settings.visible;
+    }
+
+    if !settings.visible {
+        return;
+    }
+
+    if let Some(image) = images.get_mut(handle) {
+        *image = heatmap.layer_image(settings.layer, vehicle_query.iter());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cells_cover_the_world_row_by_row() {
+        let heatmap = Heatmap::new(Vec2::new(4.0, 2.0) * HEATMAP_CELL_SIZE);
+        let half = heatmap.world_size() / 2.0;
+
+        assert_eq!(heatmap.size, UVec2::new(4, 2));
+        assert_eq!(heatmap.cell(-half), Some(0));
+        assert_eq!(heatmap.cell(Vec2::ZERO), Some(6));
+        assert_eq!(heatmap.cell(half - Vec2::splat(0.01)), Some(7));
+    }
+
+    #[test]
+    fn positions_outside_have_no_cell() {
+        let heatmap = Heatmap::new(Vec2::new(4.0, 2.0) * HEATMAP_CELL_SIZE);
+        let half = heatmap.world_size() / 2.0;
+
+        assert_eq!(heatmap.cell(-half - Vec2::new(0.01, 0.0)), None);
+        assert_eq!(heatmap.cell(-half - Vec2::new(0.0, 0.01)), None);
+        assert_eq!(heatmap.cell(Vec2::new(half.x, 0.0)), None);
+        assert_eq!(heatmap.cell(Vec2::new(0.0, half.y)), None);
+    }
+}
@@ -0,0 +1,51 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext, EguiPlugin};
+
+use crate::world::WALL_MARGIN;
+
+pub struct HudPlugin;
+
+// Status lines shown in the top left corner, keyed by the module that owns them
+#[derive(Resource, Default)]
+pub struct Hud(BTreeMap<&'static str, String>);
+
+impl Hud {
+    pub fn set(&mut self, key: &'static str, line: impl Into<String>) {
+        self.0.insert(key, line.into());
+    }
+
+    pub fn clear(&mut self, key: &'static str) {
+        self.0.remove(key);
+    }
+}
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<EguiPlugin>() {
+            app.add_plugin(EguiPlugin);
+        }
+
+        app.init_resource::<Hud>().add_system(draw_hud);
+    }
+}
+
+fn draw_hud(mut egui_context: ResMut<EguiContext>, hud: Res<Hud>) {
+    if hud.0.is_empty() {
+        return;
+    }
+
+    egui::Area::new("hud")
+        .anchor(egui::Align2::LEFT_TOP, egui::vec2(WALL_MARGIN, WALL_MARGIN))
+        .interactable(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            for line in hud.0.values() {
+                ui.label(
+                    egui::RichText::new(line)
+                        .color(egui::Color32::WHITE)
+                        .monospace(),
+                );
+            }
+        });
+}
@@ -0,0 +1,254 @@
+use bevy::prelude::*;
+
+use crate::{
+    actions::{Action, Actions},
+    brush::SpawnBrush,
+    clock::simulating,
+    closest_within, cursor_world_position,
+    formations::FormationMember,
+    hud::Hud,
+    snapshot::SnapshotApp,
+    teams::TEAM_COUNT,
+    vehicle::{
+        steer, Acceleration, Mass, Steering, SteeringForce, Team, Vehicle, VehicleSystem, Velocity,
+        VEHICLE_MAX_SPEED, VEHICLE_SIZE,
+    },
+    MainCamera,
+};
+
+pub const LEADER_BODY_COLOR: Color = Color::GOLD;
+const LEADER_SELECT_RADIUS: f32 = 20.0;
+
+// Followers arrive at a point this far behind the leader
+const LEADER_BEHIND_DIST: f32 = VEHICLE_SIZE * 8.0;
+// Slowing down within this distance of the point behind the leader
+const LEADER_ARRIVE_RADIUS: f32 = VEHICLE_SIZE * 15.0;
+// Followers in this radius around the leader or the point ahead of it get out of its way
+const LEADER_SIGHT_RADIUS: f32 = VEHICLE_SIZE * 6.0;
+const FOLLOWER_SEPARATION_DIST: f32 = VEHICLE_SIZE * 5.0;
+
+// Force factors
+const LEADER_FOLLOW_FACTOR: f32 = 2.2;
+const LEADER_EVADE_FACTOR: f32 = 2.5;
+const FOLLOWER_SEPARATION_FACTOR: f32 = 1.8;
+
+pub struct LeaderPlugin;
+
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Leader;
+
+// Teams whose vehicles follow the leaders of their team
+#[derive(Resource, Reflect, Default, Clone)]
+pub struct Following {
+    pub teams: [bool; TEAM_COUNT],
+}
+
+impl Following {
+    fn follows(&self, team: Team) -> bool {
+        self.teams[team.0 as usize % TEAM_COUNT]
+    }
+}
+
+impl Plugin for LeaderPlugin {
+    fn build(&self, app: &mut App) {
+        app.snapshot_component::<Leader>()
+            .init_resource::<Following>()
+            .add_system(select_leaders)
+            .add_system(following_controls)
+            .add_system(leader_status)
+            .add_system(
+                follow_leaders
+                    .with_run_criteria(simulating)
+                    .label(VehicleSystem::Steer)
+                    .after(VehicleSystem::Flock),
+            );
+    }
+}
+
+// Makes the vehicle closest to the cursor a leader of its team, or a follower again
+fn select_leaders(
+    mut commands: Commands,
+    vehicle_query: Query<(Entity, &Transform, Option<&Leader>), With<Vehicle>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    windows: Res<Windows>,
+    actions: Res<Actions>,
+) {
+    if !actions.just_pressed(Action::Leader) {
+        return;
+    }
+
+    let window = windows.get_primary().unwrap();
+    let (camera, camera_transform) = camera_query.single();
+
+    let world_pos = match cursor_world_position(window, camera, camera_transform) {
+        Some(world_pos) => world_pos,
+        None => return,
+    };
+
+    let closest = closest_within(
+        vehicle_query.iter().map(|(entity, transform, leader)| {
+            (transform.translation.truncate(), (entity, leader.is_some()))
+        }),
+        world_pos,
+        LEADER_SELECT_RADIUS,
+    );
+
+    if let Some((entity, leader)) = closest {
+        if leader {
+            commands.entity(entity).remove::<Leader>();
+        } else {
+            commands.entity(entity).insert(Leader);
+        }
+    }
+}
+
+fn following_controls(
+    mut following: ResMut<Following>,
+    brush: Res<SpawnBrush>,
+    actions: Res<Actions>,
+) {
+    if actions.just_pressed(Action::FollowLeader) {
+        let team = brush.team.0 as usize % TEAM_COUNT;
+        following.teams[team] = !following.teams[team];
+    }
+}
+
+// Reynolds' leader following: arrive behind the closest leader of the team, get out of the
+// way when in front of it and keep apart from the other followers
+fn follow_leaders(
+    mut vehicle_query: Query<
+        (
+            Entity,
+            &Transform,
+            &Velocity,
+            &Team,
+            &mut Acceleration,
+            &Mass,
+            &mut Steering,
+        ),
+        (With<Vehicle>, Without<Leader>, Without<FormationMember>),
+    >,
+    leader_query: Query<(&Transform, &Velocity, &Team), (With<Vehicle>, With<Leader>)>,
+    following: Res<Following>,
+) {
+    if leader_query.is_empty() || !following.teams.contains(&true) {
+        return;
+    }
+
+    let followers: Vec<(Entity, Vec2, Team)> = vehicle_query
+        .iter()
+        .filter(|(_, _, _, team, ..)| following.follows(**team))
+        .map(|(entity, transform, _, team, ..)| (entity, transform.translation.truncate(), *team))
+        .collect();
+
+    vehicle_query.for_each_mut(
+        |(entity, transform, velocity, team, mut acceleration, mass, mut steering)| {
+            if !following.follows(*team) {
+                return;
+            }
+
+            let position = transform.translation.truncate();
+
+            let leader = leader_query
+                .iter()
+                .filter(|(_, _, leader_team)| *leader_team == team)
+                .map(|(leader_transform, leader_velocity, _)| {
+                    (leader_transform.translation.truncate(), leader_velocity.0)
+                })
+                .min_by(|(a, _), (b, _)| {
+                    a.distance_squared(position)
+                        .total_cmp(&b.distance_squared(position))
+                });
+
+            let (leader_position, leader_velocity) = match leader {
+                Some(leader) => leader,
+                None => return,
+            };
+
+            let heading = leader_velocity.normalize_or_zero();
+            let behind = leader_position - heading * LEADER_BEHIND_DIST;
+            let ahead = leader_position + heading * LEADER_BEHIND_DIST;
+
+            // Arrive
+            let offset = behind - position;
+            let dist = offset.length();
+            let speed = VEHICLE_MAX_SPEED * (dist / LEADER_ARRIVE_RADIUS).min(1.0);
+
+            let force =
+                steer(offset.normalize_or_zero() * speed, velocity.0) * LEADER_FOLLOW_FACTOR;
+            steering.record(SteeringForce::Follow, force);
+            acceleration.apply_force(force, mass);
+
+            // Evade the leader while in its path
+            if ahead.distance(position) <= LEADER_SIGHT_RADIUS
+                || leader_position.distance(position) <= LEADER_SIGHT_RADIUS
+            {
+                let time = leader_position.distance(position) / VEHICLE_MAX_SPEED;
+                let threat = leader_position + leader_velocity * time;
+                let desired = (position - threat).normalize_or_zero() * VEHICLE_MAX_SPEED;
+
+                let force = steer(desired, velocity.0) * LEADER_EVADE_FACTOR;
+                steering.record(SteeringForce::Evade, force);
+                acceleration.apply_force(force, mass);
+            }
+
+            // Separate from the other followers of the team
+            let mut separate_sum = Vec2::ZERO;
+            let mut separate_count = 0;
+
+            for (other, other_position, other_team) in followers.iter() {
+                let dist = other_position.distance(position);
+
+                if *other != entity
+                    && other_team == team
+                    && dist > 0.0
+                    && dist <= FOLLOWER_SEPARATION_DIST
+                {
+                    separate_sum += (position - *other_position).normalize_or_zero() / dist;
+                    separate_count += 1;
+                }
+            }
+
+            if separate_count > 0 {
+                let desired = separate_sum.normalize_or_zero() * VEHICLE_MAX_SPEED;
+
+                let force = steer(desired, velocity.0) * FOLLOWER_SEPARATION_FACTOR;
+                steering.record(SteeringForce::Separation, force);
+                acceleration.apply_force(force, mass);
+            }
+        },
+    );
+}
+
+fn leader_status(
+    mut hud: ResMut<Hud>,
+    following: Res<Following>,
+    leader_query: Query<(), With<Leader>>,
+) {
+    let teams: Vec<String> = following
+        .teams
+        .iter()
+        .enumerate()
+        .filter(|(_, follows)| **follows)
+        .map(|(team, _)| team.to_string())
+        .collect();
+
+    if teams.is_empty() && leader_query.is_empty() {
+        hud.clear("leaders");
+        return;
+    }
+
+    hud.set(
+        "leaders",
+        format!(
+            "Leaders {}, followed by teams {}",
+            leader_query.iter().len(),
+            if teams.is_empty() {
+                "none".to_string()
+            } else {
+                teams.join(", ")
+            }
+        ),
+    );
+}
@@ -1,13 +1,61 @@
 // REFERENCE https://natureofcode.com/book/chapter-6-autonomous-agents/
+mod actions;
+mod batching;
+mod brush;
+mod clock;
+mod coloring;
 mod debug;
+mod emitters;
+mod energy;
+mod evolution;
+mod formations;
+mod gamepad;
+mod heatmap;
+mod hud;
+mod leaders;
+mod placeables;
+mod predators;
+mod recorder;
+mod replay;
+mod rewind;
+mod rng;
+mod sinks;
+mod snapshot;
 mod target;
+mod teams;
+mod touch;
+mod trails;
 mod vehicle;
 mod world;
 
+use actions::ActionPlugin;
+use batching::BatchingPlugin;
 use bevy::{prelude::*, render::camera::ScalingMode};
 use bevy_prototype_lyon::prelude::*;
+use brush::BrushPlugin;
+use clock::ClockPlugin;
+use coloring::ColoringPlugin;
 use debug::DebugPlugin;
+use emitters::EmitterPlugin;
+use energy::EnergyPlugin;
+use evolution::EvolutionPlugin;
+use formations::FormationPlugin;
+use gamepad::GamepadPlugin;
+use heatmap::HeatmapPlugin;
+use hud::HudPlugin;
+use leaders::LeaderPlugin;
+use placeables::PlaceablePlugin;
+use predators::PredatorPlugin;
+use recorder::RecorderPlugin;
+use replay::ReplayPlugin;
+use rewind::RewindPlugin;
+use rng::SimRng;
+use sinks::SinkPlugin;
+use snapshot::SnapshotPlugin;
 use target::TargetPlugin;
+use teams::TeamPlugin;
+use touch::TouchPlugin;
+use trails::TrailPlugin;
 use vehicle::VehiclePlugin;
 use world::WorldPlugin;
 
@@ -17,6 +65,7 @@ fn main() {
     App::new()
         .insert_resource(ClearColor(CLEAR))
         .insert_resource(Msaa { samples: 4 })
+        .init_resource::<SimRng>()
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             window: WindowDescriptor {
                 title: "3D Particle simulation".to_string(),
@@ -29,12 +78,34 @@ fn main() {
             ..default()
         }))
         .add_startup_system(spawn_camera)
-        .add_startup_system(hide_cursor)
         .add_plugin(DebugPlugin)
+        .add_plugin(ActionPlugin)
+        .add_plugin(HudPlugin)
+        .add_plugin(ClockPlugin)
         .add_plugin(WorldPlugin)
         .add_plugin(ShapePlugin)
         .add_plugin(VehiclePlugin)
         .add_plugin(TargetPlugin)
+        .add_plugin(BrushPlugin)
+        .add_plugin(SinkPlugin)
+        .add_plugin(EmitterPlugin)
+        .add_plugin(PlaceablePlugin)
+        .add_plugin(PredatorPlugin)
+        .add_plugin(EnergyPlugin)
+        .add_plugin(EvolutionPlugin)
+        .add_plugin(TeamPlugin)
+        .add_plugin(LeaderPlugin)
+        .add_plugin(FormationPlugin)
+        .add_plugin(TouchPlugin)
+        .add_plugin(GamepadPlugin)
+        .add_plugin(RecorderPlugin)
+        .add_plugin(ReplayPlugin)
+        .add_plugin(SnapshotPlugin)
+        .add_plugin(RewindPlugin)
+        .add_plugin(TrailPlugin)
+        .add_plugin(HeatmapPlugin)
+        .add_plugin(ColoringPlugin)
+        .add_plugin(BatchingPlugin)
         .run();
 }
 
@@ -57,7 +128,43 @@ fn spawn_camera(mut commands: Commands) {
         .insert(MainCamera);
 }
 
-fn hide_cursor(mut windows: ResMut<Windows>) {
-    let window = windows.get_primary_mut().unwrap();
-    window.set_cursor_visibility(false);
+pub fn cursor_world_position(
+    window: &Window,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+) -> Option<Vec2> {
+    Some(screen_to_world(
+        window,
+        camera,
+        camera_transform,
+        window.cursor_position()?,
+    ))
+}
+
+// Picks whatever lies closest to `world_pos`, as long as it is within `radius`
+pub fn closest_within<T>(
+    items: impl Iterator<Item = (Vec2, T)>,
+    world_pos: Vec2,
+    radius: f32,
+) -> Option<T> {
+    items
+        .map(|(position, item)| (position.distance_squared(world_pos), item))
+        .filter(|(dist, _)| *dist <= radius * radius)
+        .min_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, item)| item)
+}
+
+// `position` is in logical pixels from the bottom left corner of the window
+pub fn screen_to_world(
+    window: &Window,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    position: Vec2,
+) -> Vec2 {
+    let window_size = Vec2::new(window.width() as f32, window.height() as f32);
+
+    let ndc = (position / window_size) * 2.0 - Vec2::ONE;
+    let ndc_to_world = camera_transform.compute_matrix() * camera.projection_matrix().inverse();
+
+    ndc_to_world.project_point3(ndc.extend(-1.0)).truncate()
 }
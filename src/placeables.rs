@@ -0,0 +1,105 @@
+use bevy::{input::mouse::MouseWheel, prelude::*};
+
+use crate::{
+    cursor_world_position,
+    emitters::{Emitter, EMITTER_HOVER_RADIUS},
+    sinks::Sink,
+    target::TargetField,
+    MainCamera,
+};
+
+pub struct PlaceablePlugin;
+
+// Object placed in the world that the mouse wheel scrolled this frame, with the scrolled steps
+#[derive(Resource, Default)]
+pub struct PlaceableScroll(pub Option<(Entity, f32)>);
+
+impl Plugin for PlaceablePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlaceableScroll>()
+            .add_system(scroll_placeables);
+    }
+}
+
+// Closest object whose radius the cursor is inside of
+pub fn hovered<'a>(
+    world_pos: Vec2,
+    objects: impl Iterator<Item = (Entity, &'a Transform, f32)>,
+) -> Option<Entity> {
+    objects
+        .map(|(entity, transform, radius)| {
+            (
+                entity,
+                transform.translation.truncate().distance(world_pos),
+                radius,
+            )
+        })
+        .filter(|(_, dist, radius)| dist <= radius)
+        .min_by(|(_, a, _), (_, b, _)| a.total_cmp(b))
+        .map(|(entity, ..)| entity)
+}
+
+// Only the closest of overlapping fields, sinks and emitters takes the scroll
+pub fn scroll_placeables(
+    mut scroll: ResMut<PlaceableScroll>,
+    field_query: Query<(Entity, &Transform, &TargetField)>,
+    sink_query: Query<(Entity, &Transform, &Sink)>,
+    emitter_query: Query<(Entity, &Transform), With<Emitter>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    windows: Res<Windows>,
+    mut scroll_events: EventReader<MouseWheel>,
+) {
+    scroll.0 = None;
+
+    let steps: f32 = scroll_events.iter().map(|event| event.y.signum()).sum();
+    if steps == 0.0 {
+        return;
+    }
+
+    let window = windows.get_primary().unwrap();
+    let (camera, camera_transform) = camera_query.single();
+
+    let world_pos = match cursor_world_position(window, camera, camera_transform) {
+        Some(world_pos) => world_pos,
+        None => return,
+    };
+
+    let fields = field_query
+        .iter()
+        .map(|(entity, transform, field)| (entity, transform, field.radius));
+    let sinks = sink_query
+        .iter()
+        .map(|(entity, transform, sink)| (entity, transform, sink.radius));
+    let emitters = emitter_query
+        .iter()
+        .map(|(entity, transform)| (entity, transform, EMITTER_HOVER_RADIUS));
+
+    scroll.0 =
+        hovered(world_pos, fields.chain(sinks).chain(emitters)).map(|entity| (entity, steps));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closest_object_containing_the_cursor_is_hovered() {
+        let near = Entity::from_raw(1);
+        let far = Entity::from_raw(2);
+        let small = Entity::from_raw(3);
+
+        let objects = [
+            (far, Transform::from_xyz(8.0, 0.0, 0.0), 50.0),
+            (near, Transform::from_xyz(4.0, 0.0, 0.0), 50.0),
+            (small, Transform::from_xyz(1.0, 0.0, 0.0), 0.5),
+        ];
+        let iter = || {
+            objects
+                .iter()
+                .map(|(entity, transform, radius)| (*entity, transform, *radius))
+        };
+
+        assert_eq!(hovered(Vec2::ZERO, iter()), Some(near));
+        assert_eq!(hovered(Vec2::new(100.0, 0.0), iter()), None);
+    }
+}
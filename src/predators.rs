@@ -0,0 +1,215 @@
+use bevy::prelude::*;
+
+use crate::{
+    clock::simulating,
+    energy::Energy,
+    formations::FormationMember,
+    hud::Hud,
+    vehicle::{
+        steer, Acceleration, DespawnSystem, Despawned, Mass, Steering, SteeringForce, Vehicle,
+        VehicleProfile, VehicleSystem, Velocity, VEHICLE_MAX_SPEED, VEHICLE_SIZE,
+    },
+};
+
+const PREDATOR_PERCEPTION: f32 = VEHICLE_SIZE * 50.0;
+const PREDATOR_PERCEPTION_SQ: f32 = PREDATOR_PERCEPTION * PREDATOR_PERCEPTION;
+const PREY_PERCEPTION: f32 = VEHICLE_SIZE * 30.0;
+const PREDATOR_CAPTURE_DIST: f32 = VEHICLE_SIZE * 2.0;
+const PREDATOR_CAPTURE_DIST_SQ: f32 = PREDATOR_CAPTURE_DIST * PREDATOR_CAPTURE_DIST;
+
+// Force factors
+const PREDATOR_PURSUE_FACTOR: f32 = 2.0;
+const PREY_EVADE_FACTOR: f32 = 3.0;
+
+// Energy a predator gets out of a catch
+const PREDATOR_MEAL_ENERGY: f32 = 40.0;
+
+// Longest look ahead, in seconds, when predicting where the other vehicle goes
+const PREDICTION_LIMIT: f32 = 1.0;
+
+pub struct PredatorPlugin;
+
+#[derive(Resource, Default)]
+pub struct Hunt {
+    pub captures: usize,
+}
+
+impl Plugin for PredatorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Hunt>()
+            .add_system(hunt_status)
+            .add_system(
+                hunt.with_run_criteria(simulating)
+                    .label(VehicleSystem::Steer)
+                    .after(VehicleSystem::Flock),
+            )
+            // Despawned after the update stage, like the vehicles caught by sinks
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                capture
+                    .with_run_criteria(simulating)
+                    .label(DespawnSystem::Capture)
+                    .after(DespawnSystem::Absorb),
+            );
+    }
+}
+
+fn is_predator(profile: &VehicleProfile) -> bool {
+    *profile == VehicleProfile::Predator
+}
+
+// Where the other vehicle will be by the time this one could reach it
+fn predict(position: Vec2, other_position: Vec2, other_velocity: Vec2) -> Vec2 {
+    let time = (position.distance(other_position) / VEHICLE_MAX_SPEED).min(PREDICTION_LIMIT);
+    other_position + other_velocity * time
+}
+
+// Predators pursue the closest prey they see, prey evades every predator it sees
+fn hunt(
+    mut vehicle_query: Query<
+        (
+            &Transform,
+            &Velocity,
+            &VehicleProfile,
+            &mut Acceleration,
+            &Mass,
+            &mut Steering,
+        ),
+        (With<Vehicle>, Without<FormationMember>),
+    >,
+    other_query: Query<(&Transform, &Velocity, &VehicleProfile), With<Vehicle>>,
+) {
+    let mut predators = Vec::new();
+    let mut prey = Vec::new();
+
+    // Formation members only hold their slot, but can still be hunted
+    for (transform, velocity, profile) in other_query.iter() {
+        let state = (transform.translation.truncate(), velocity.0);
+
+        if is_predator(profile) {
+            predators.push(state);
+        } else {
+            prey.push(state);
+        }
+    }
+
+    if predators.is_empty() {
+        return;
+    }
+
+    vehicle_query.for_each_mut(
+        |(transform, velocity, profile, mut acceleration, mass, mut steering)| {
+            let position = transform.translation.truncate();
+
+            if is_predator(profile) {
+                let nearest = prey
+                    .iter()
+                    .map(|(prey_position, prey_velocity)| {
+                        (
+                            prey_position.distance_squared(position),
+                            *prey_position,
+                            *prey_velocity,
+                        )
+                    })
+                    .filter(|(dist_sq, ..)| *dist_sq <= PREDATOR_PERCEPTION_SQ)
+                    .min_by(|(a, ..), (b, ..)| a.total_cmp(b));
+
+                if let Some((_, prey_position, prey_velocity)) = nearest {
+                    let desired = (predict(position, prey_position, prey_velocity) - position)
+                        .normalize_or_zero()
+                        * VEHICLE_MAX_SPEED;
+
+                    let force = steer(desired, velocity.0) * PREDATOR_PURSUE_FACTOR;
+                    steering.record(SteeringForce::Pursue, force);
+                    acceleration.apply_force(force, mass);
+                }
+
+                return;
+            }
+
+            for (predator_position, predator_velocity) in predators.iter() {
+                let dist = predator_position.distance(position);
+                if dist > PREY_PERCEPTION {
+                    continue;
+                }
+
+                let threat = predict(position, *predator_position, *predator_velocity);
+                let desired = (position - threat).normalize_or_zero() * VEHICLE_MAX_SPEED;
+
+                // Closer predators are more urgent
+                let force =
+                    steer(desired, velocity.0) * PREY_EVADE_FACTOR * (1.0 - dist / PREY_PERCEPTION);
+                steering.record(SteeringForce::Evade, force);
+                acceleration.apply_force(force, mass);
+            }
+        },
+    );
+}
+
+fn capture(
+    mut commands: Commands,
+    mut hunt: ResMut<Hunt>,
+    mut despawned: ResMut<Despawned>,
+    vehicle_query: Query<(Entity, &Transform, &VehicleProfile), With<Vehicle>>,
+    mut energy_query: Query<&mut Energy>,
+) {
+    let predators: Vec<(Entity, Vec2)> = vehicle_query
+        .iter()
+        .filter(|(entity, _, profile)| is_predator(profile) && !despawned.contains(*entity))
+        .map(|(entity, transform, _)| (entity, transform.translation.truncate()))
+        .collect();
+
+    if predators.is_empty() {
+        return;
+    }
+
+    for (entity, transform, profile) in vehicle_query.iter() {
+        if is_predator(profile) {
+            continue;
+        }
+
+        let position = transform.translation.truncate();
+
+        let predator = predators
+            .iter()
+            .find(|(_, predator)| predator.distance_squared(position) <= PREDATOR_CAPTURE_DIST_SQ);
+
+        if let Some((predator, _)) = predator {
+            if !despawned.take(&mut commands, entity) {
+                continue;
+            }
+
+            hunt.captures += 1;
+
+            if let Ok(mut energy) = energy_query.get_mut(*predator) {
+                energy.gain(PREDATOR_MEAL_ENERGY);
+            }
+        }
+    }
+}
+
+fn hunt_status(
+    mut hud: ResMut<Hud>,
+    hunt: Res<Hunt>,
+    vehicle_query: Query<&VehicleProfile, With<Vehicle>>,
+) {
+    let predators = vehicle_query
+        .iter()
+        .filter(|profile| is_predator(profile))
+        .count();
+
+    if predators == 0 && hunt.captures == 0 {
+        hud.clear("hunt");
+        return;
+    }
+
+    hud.set(
+        "hunt",
+        format!(
+            "Predators {}, prey {}, captured {}",
+            predators,
+            vehicle_query.iter().len() - predators,
+            hunt.captures
+        ),
+    );
+}
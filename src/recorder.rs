@@ -0,0 +1,473 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    actions::{Action, Actions},
+    clock::{simulating, SimClock},
+    closest_within, cursor_world_position,
+    hud::Hud,
+    vehicle::{Acceleration, Team, Vehicle, VehicleProfile, VehicleSystem, Velocity},
+    MainCamera,
+};
+
+const RECORDER_PATH: &str = "trajectory";
+// Ticks between samples to choose from
+const RECORDER_SAMPLE_RATES: [u32; 5] = [1, 2, 5, 10, 30];
+const RECORDER_TAG_RADIUS: f32 = 20.0;
+// Buffered samples are written out this often, and when the recording stops
+const RECORDER_FLUSH_TICKS: u64 = 60;
+
+pub struct RecorderPlugin;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordFormat {
+    Csv,
+    Ndjson,
+}
+
+impl RecordFormat {
+    fn next(&self) -> Self {
+        match self {
+            RecordFormat::Csv => RecordFormat::Ndjson,
+            RecordFormat::Ndjson => RecordFormat::Csv,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RecordFormat::Csv => "csv",
+            RecordFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordFilter {
+    All,
+    // Only vehicles carrying the `Recorded` marker
+    Tagged,
+}
+
+impl RecordFilter {
+    fn next(&self) -> Self {
+        match self {
+            RecordFilter::All => RecordFilter::Tagged,
+            RecordFilter::Tagged => RecordFilter::All,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Recorded;
+
+#[derive(Resource)]
+pub struct RecorderConfig {
+    pub format: RecordFormat,
+    // Write a sample every n-th tick
+    pub sample_every: u32,
+    pub filter: RecordFilter,
+    // Output path without the extension, which is taken from `format`
+    pub path: PathBuf,
+}
+
+impl Default for RecorderConfig {
+    fn default() -> Self {
+        Self {
+            format: RecordFormat::Csv,
+            sample_every: 1,
+            filter: RecordFilter::All,
+            path: PathBuf::from(RECORDER_PATH),
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Recorder {
+    writer: Option<BufWriter<File>>,
+    format: Option<RecordFormat>,
+    tick: u64,
+    flushed_tick: u64,
+}
+
+impl Recorder {
+    pub fn is_recording(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    pub fn start(&mut self, config: &RecorderConfig) {
+        let path = config.path.with_extension(config.format.extension());
+
+        let mut writer = match File::create(&path) {
+            Ok(file) => BufWriter::new(file),
+            Err(err) => {
+                error!("Failed to create recording {:?}: {}", path, err);
+                return;
+            }
+        };
+
+        if config.format == RecordFormat::Csv {
+            if let Err(err) = writeln!(writer, "tick,time,id,x,y,vx,vy,ax,ay,team,profile") {
+                error!("Failed to write recording header {:?}: {}", path, err);
+                return;
+            }
+        }
+
+        info!("Recording trajectories to {:?}", path);
+
+        self.writer = Some(writer);
+        self.format = Some(config.format);
+        self.tick = 0;
+        self.flushed_tick = 0;
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(mut writer) = self.writer.take() {
+            if let Err(err) = writer.flush() {
+                error!("Failed to flush recording: {}", err);
+            }
+            info!("Recording stopped after {} ticks", self.tick);
+        }
+        self.format = None;
+    }
+}
+
+impl Plugin for RecorderPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RecorderConfig>()
+            .init_resource::<Recorder>()
+            .add_system(toggle_recording)
+            .add_system(recorder_controls)
+            .add_system(tag_vehicles)
+            .add_system(
+                recorder_status
+                    .after(toggle_recording)
+                    .after(recorder_controls)
+                    .after(tag_vehicles),
+            )
+            .add_system(
+                record
+                    .with_run_criteria(simulating)
+                    .after(VehicleSystem::Steer)
+                    .before(VehicleSystem::Move),
+            );
+    }
+}
+
+fn toggle_recording(
+    mut recorder: ResMut<Recorder>,
+    config: Res<RecorderConfig>,
+    actions: Res<Actions>,
+) {
+    if !actions.just_pressed(Action::Record) {
+        return;
+    }
+
+    if recorder.is_recording() {
+        recorder.stop();
+    } else {
+        recorder.start(&config);
+    }
+}
+
+// The format applies from the next recording, the rate and filter right away
+fn recorder_controls(mut config: ResMut<RecorderConfig>, actions: Res<Actions>) {
+    if actions.just_pressed(Action::RecordFormat) {
+        config.format = config.format.next();
+    }
+
+    if actions.just_pressed(Action::RecordRate) {
+        let next = RECORDER_SAMPLE_RATES
+            .iter()
+            .position(|rate| *rate == config.sample_every)
+            .map_or(0, |index| (index + 1) % RECORDER_SAMPLE_RATES.len());
+        config.sample_every = RECORDER_SAMPLE_RATES[next];
+    }
+
+    if actions.just_pressed(Action::RecordFilter) {
+        config.filter = config.filter.next();
+    }
+}
+
+// Tags the vehicle closest to the cursor, or untags it
+fn tag_vehicles(
+    mut commands: Commands,
+    vehicle_query: Query<(Entity, &Transform, Option<&Recorded>), With<Vehicle>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    windows: Res<Windows>,
+    actions: Res<Actions>,
+) {
+    if !actions.just_pressed(Action::RecordTag) {
+        return;
+    }
+
+    let window = windows.get_primary().unwrap();
+    let (camera, camera_transform) = camera_query.single();
+
+    let world_pos = match cursor_world_position(window, camera, camera_transform) {
+        Some(world_pos) => world_pos,
+        None => return,
+    };
+
+    let closest = closest_within(
+        vehicle_query.iter().map(|(entity, transform, recorded)| {
+            (
+                transform.translation.truncate(),
+                (entity, recorded.is_some()),
+            )
+        }),
+        world_pos,
+        RECORDER_TAG_RADIUS,
+    );
+
+    match closest {
+        Some((entity, true)) => {
+            commands.entity(entity).remove::<Recorded>();
+        }
+        Some((entity, false)) => {
+            commands.entity(entity).insert(Recorded);
+        }
+        None => {}
+    }
+}
+
+// Shown once recording or its settings were used
+fn recorder_status(
+    mut hud: ResMut<Hud>,
+    mut used: Local<bool>,
+    recorder: Res<Recorder>,
+    config: Res<RecorderConfig>,
+    recorded_query: Query<(), With<Recorded>>,
+) {
+    *used |= recorder.is_recording() || (config.is_changed() && !config.is_added());
+    if !*used {
+        return;
+    }
+
+    let filter = match config.filter {
+        RecordFilter::All => "all vehicles".to_string(),
+        RecordFilter::Tagged => format!("{} tagged vehicles", recorded_query.iter().len()),
+    };
+
+    hud.set(
+        "recorder",
+        format!(
+            "{} {} every {} ticks, {}",
+            if recorder.is_recording() {
+                "Recording"
+            } else {
+                "Recorder"
+            },
+            config.format.extension(),
+            config.sample_every,
+            filter
+        ),
+    );
+}
+
+// One line of the recording
+struct TrajectorySample {
+    tick: u64,
+    time: f32,
+    id: u64,
+    position: Vec2,
+    velocity: Vec2,
+    acceleration: Vec2,
+    team: Team,
+    profile: VehicleProfile,
+}
+
+impl TrajectorySample {
+    fn write(&self, writer: &mut impl Write, format: RecordFormat) -> io::Result<()> {
+        let Self {
+            tick,
+            time,
+            id,
+            position,
+            velocity,
+            acceleration,
+            team,
+            profile,
+        } = self;
+
+        match format {
+            RecordFormat::Csv => writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{},{},{},{:?}",
+                tick,
+                time,
+                id,
+                position.x,
+                position.y,
+                velocity.x,
+                velocity.y,
+                acceleration.x,
+                acceleration.y,
+                team.0,
+                profile
+            ),
+            RecordFormat::Ndjson => writeln!(
+                writer,
+                "{{\"tick\":{},\"time\":{},\"id\":{},\"position\":[{},{}],\"velocity\":[{},{}],\"acceleration\":[{},{}],\"team\":{},\"profile\":\"{:?}\"}}",
+                tick,
+                time,
+                id,
+                position.x,
+                position.y,
+                velocity.x,
+                velocity.y,
+                acceleration.x,
+                acceleration.y,
+                team.0,
+                profile
+            ),
+        }
+    }
+}
+
+// Runs between steering and integration so the sampled acceleration is the one
+// about to be applied to the velocity.
+fn record(
+    mut recorder: ResMut<Recorder>,
+    config: Res<RecorderConfig>,
+    vehicle_query: Query<
+        (
+            Entity,
+            &Transform,
+            &Velocity,
+            &Acceleration,
+            &Team,
+            &VehicleProfile,
+            Option<&Recorded>,
+        ),
+        With<Vehicle>,
+    >,
+    clock: Res<SimClock>,
+) {
+    let recorder = &mut *recorder;
+    let (writer, format) = match (recorder.writer.as_mut(), recorder.format) {
+        (Some(writer), Some(format)) => (writer, format),
+        _ => return,
+    };
+
+    let tick = recorder.tick;
+    recorder.tick += 1;
+
+    if tick % config.sample_every.max(1) as u64 != 0 {
+        return;
+    }
+
+    let elapsed = clock.elapsed_seconds();
+    let mut result = Ok(());
+
+    for (entity, transform, velocity, acceleration, team, profile, recorded) in vehicle_query.iter()
+    {
+        if config.filter == RecordFilter::Tagged && recorded.is_none() {
+            continue;
+        }
+
+        result = TrajectorySample {
+            tick,
+            time: elapsed,
+            // Unlike the index alone, the bits are not shared with vehicles despawned earlier
+            id: entity.to_bits(),
+            position: transform.translation.truncate(),
+            velocity: velocity.0,
+            acceleration: acceleration.0,
+            team: *team,
+            profile: *profile,
+        }
+        .write(writer, format);
+
+        if result.is_err() {
+            break;
+        }
+    }
+
+    if tick - recorder.flushed_tick >= RECORDER_FLUSH_TICKS {
+        result = result.and_then(|_| writer.flush());
+        recorder.flushed_tick = tick;
+    }
+
+    if let Err(err) = result {
+        error!("Failed to write recording: {}", err);
+        recorder.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+    use crate::replay::load_trajectory;
+
+    fn sample(tick: u64) -> TrajectorySample {
+        TrajectorySample {
+            tick,
+            time: tick as f32 * 0.5,
+            id: 4_294_967_297,
+            position: Vec2::new(1.5, -2.0),
+            velocity: Vec2::new(3.0, 4.0),
+            acceleration: Vec2::new(-0.25, 0.0),
+            team: Team(2),
+            profile: VehicleProfile::Heavy,
+        }
+    }
+
+    #[test]
+    fn csv_has_one_column_per_header_field() {
+        let mut line = Vec::new();
+        sample(3).write(&mut line, RecordFormat::Csv).unwrap();
+
+        assert_eq!(
+            String::from_utf8(line).unwrap(),
+            "3,1.5,4294967297,1.5,-2,3,4,-0.25,0,2,Heavy\n"
+        );
+    }
+
+    #[test]
+    fn recordings_load_back_in_both_formats() {
+        for format in [RecordFormat::Csv, RecordFormat::Ndjson] {
+            let config = RecorderConfig {
+                format,
+                path: env::temp_dir().join(format!("recorder_test_{}", std::process::id())),
+                ..Default::default()
+            };
+            let path = config.path.with_extension(format.extension());
+
+            let mut recorder = Recorder::default();
+            recorder.start(&config);
+            assert!(recorder.is_recording());
+
+            for tick in 0..3 {
+                sample(tick)
+                    .write(recorder.writer.as_mut().unwrap(), format)
+                    .unwrap();
+            }
+            recorder.stop();
+            assert!(!recorder.is_recording());
+
+            let frames = load_trajectory(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+
+            assert_eq!(frames.len(), 3);
+            assert_eq!(frames[2].time, 1.0);
+            assert_eq!(frames[2].samples[0].id, 4_294_967_297);
+            assert_eq!(frames[2].samples[0].position, Vec2::new(1.5, -2.0));
+            assert_eq!(frames[2].samples[0].velocity, Vec2::new(3.0, 4.0));
+            assert_eq!(frames[2].samples[0].team, Team(2));
+            assert_eq!(frames[2].samples[0].profile, VehicleProfile::Heavy);
+        }
+    }
+
+    #[test]
+    fn settings_cycle_back() {
+        assert_eq!(RecordFormat::Csv.next().next(), RecordFormat::Csv);
+        assert_eq!(RecordFilter::All.next().next(), RecordFilter::All);
+    }
+}
@@ -0,0 +1,491 @@
+use std::{collections::HashMap, f32::consts::PI, fs, path::Path};
+
+use bevy::{ecs::schedule::ShouldRun, prelude::*};
+
+use crate::{
+    actions::{Action, Actions},
+    recorder::{RecordFormat, RecorderConfig},
+    rewind::{VehicleState, VehicleStateQuery},
+    vehicle::{spawn_vehicle, Genome, SpawnVehicle, Team, Vehicle, VehicleProfile, Velocity},
+};
+
+const REPLAY_SEEK_STEP: f32 = 5.0;
+const REPLAY_MIN_SPEED: f32 = 0.125;
+const REPLAY_MAX_SPEED: f32 = 8.0;
+
+pub struct ReplayPlugin;
+
+#[derive(Clone, Copy)]
+pub struct ReplaySample {
+    pub id: u64,
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub team: Team,
+    pub profile: VehicleProfile,
+}
+
+pub struct ReplayFrame {
+    pub time: f32,
+    pub samples: Vec<ReplaySample>,
+}
+
+#[derive(Resource)]
+pub struct Replay {
+    pub frames: Vec<ReplayFrame>,
+    pub active: bool,
+    pub playing: bool,
+    pub time: f32,
+    pub speed: f32,
+    vehicles: HashMap<u64, Entity>,
+    // The live flock, put back once the replay is left
+    stash: Vec<VehicleState>,
+}
+
+impl Default for Replay {
+    fn default() -> Self {
+        Self {
+            frames: Vec::new(),
+            active: false,
+            playing: false,
+            time: 0.0,
+            speed: 1.0,
+            vehicles: HashMap::new(),
+            stash: Vec::new(),
+        }
+    }
+}
+
+impl Replay {
+    pub fn duration(&self) -> f32 {
+        self.frames.last().map_or(0.0, |frame| frame.time)
+    }
+
+    pub fn seek(&mut self, time: f32) {
+        self.time = time.clamp(0.0, self.duration());
+    }
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Replay>()
+            .add_system(toggle_replay)
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(replaying)
+                    .with_system(replay_controls)
+                    .with_system(drive_vehicles.after(replay_controls)),
+            );
+    }
+}
+
+pub fn replaying(replay: Res<Replay>) -> ShouldRun {
+    if replay.active {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+pub fn not_replaying(replay: Res<Replay>) -> ShouldRun {
+    if replay.active {
+        ShouldRun::No
+    } else {
+        ShouldRun::Yes
+    }
+}
+
+pub fn load_trajectory(path: &Path) -> Result<Vec<ReplayFrame>, String> {
+    let contents = fs::read_to_string(path).map_err(|err| format!("{:?}: {}", path, err))?;
+    parse_trajectory(path, &contents)
+}
+
+// The format is taken from the extension of `path`, which also prefixes errors
+fn parse_trajectory(path: &Path, contents: &str) -> Result<Vec<ReplayFrame>, String> {
+    let ndjson =
+        path.extension().and_then(|ext| ext.to_str()) == Some(RecordFormat::Ndjson.extension());
+
+    let mut frames: Vec<ReplayFrame> = Vec::new();
+    let mut last_tick = None;
+
+    for (line_number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("tick") {
+            continue;
+        }
+
+        let parsed = if ndjson {
+            parse_ndjson_line(line)
+        } else {
+            parse_csv_line(line)
+        };
+
+        let (tick, time, sample) =
+            parsed.ok_or_else(|| format!("{:?}:{}: malformed sample", path, line_number + 1))?;
+
+        if last_tick != Some(tick) {
+            frames.push(ReplayFrame {
+                time,
+                samples: Vec::new(),
+            });
+            last_tick = Some(tick);
+        }
+
+        frames.last_mut().unwrap().samples.push(sample);
+    }
+
+    // Replay time starts at the first recorded frame
+    if let Some(start) = frames.first().map(|frame| frame.time) {
+        frames.iter_mut().for_each(|frame| frame.time -= start);
+    }
+
+    Ok(frames)
+}
+
+// tick,time,id,x,y,vx,vy,ax,ay,team,profile
+fn parse_csv_line(line: &str) -> Option<(u64, f32, ReplaySample)> {
+    let mut fields = line.split(',').map(str::trim);
+
+    let tick = fields.next()?.parse().ok()?;
+    let time = fields.next()?.parse().ok()?;
+    let id = fields.next()?.parse().ok()?;
+
+    let mut next_f32 = || fields.next()?.parse::<f32>().ok();
+    let position = Vec2::new(next_f32()?, next_f32()?);
+    let velocity = Vec2::new(next_f32()?, next_f32()?);
+    let _acceleration = Vec2::new(next_f32()?, next_f32()?);
+
+    let team = parse_team(fields.next())?;
+    let profile = parse_profile(fields.next())?;
+
+    Some((
+        tick,
+        time,
+        ReplaySample {
+            id,
+            position,
+            velocity,
+            team,
+            profile,
+        },
+    ))
+}
+
+fn parse_ndjson_line(line: &str) -> Option<(u64, f32, ReplaySample)> {
+    let tick = json_value(line, "tick")?.parse().ok()?;
+    let time = json_value(line, "time")?.parse().ok()?;
+    let id = json_value(line, "id")?.parse().ok()?;
+
+    Some((
+        tick,
+        time,
+        ReplaySample {
+            id,
+            position: json_vec2(line, "position")?,
+            velocity: json_vec2(line, "velocity")?,
+            team: parse_team(json_value(line, "team"))?,
+            profile: parse_profile(
+                json_value(line, "profile").map(|value| value.trim_matches('"')),
+            )?,
+        },
+    ))
+}
+
+// Recordings made before teams and profiles were written default to the first of each
+fn parse_team(value: Option<&str>) -> Option<Team> {
+    value.map_or(Some(Team::default()), |value| value.parse().ok().map(Team))
+}
+
+fn parse_profile(value: Option<&str>) -> Option<VehicleProfile> {
+    let value = match value {
+        Some(value) => value,
+        None => return Some(VehicleProfile::default()),
+    };
+
+    std::iter::successors(Some(VehicleProfile::Primary), |profile| {
+        Some(profile.next()).filter(|next| *next != VehicleProfile::Primary)
+    })
+    .find(|profile| format!("{:?}", profile) == value)
+}
+
+// Only understands the flat objects written by the recorder
+fn json_value<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let pattern = format!("\"{}\":", key);
+    let start = line.find(&pattern)? + pattern.len();
+    let rest = &line[start..];
+
+    let end = if rest.starts_with('[') {
+        rest.find(']')? + 1
+    } else {
+        rest.find(|c: char| c == ',' || c == '}')?
+    };
+
+    Some(rest[..end].trim())
+}
+
+fn json_vec2(line: &str, key: &str) -> Option<Vec2> {
+    let value = json_value(line, key)?;
+    let mut parts = value
+        .trim_matches(|c: char| c == '[' || c == ']')
+        .split(',');
+
+    let x = parts.next()?.trim().parse().ok()?;
+    let y = parts.next()?.trim().parse().ok()?;
+
+    Some(Vec2::new(x, y))
+}
+
+fn toggle_replay(
+    mut commands: Commands,
+    mut replay: ResMut<Replay>,
+    config: Res<RecorderConfig>,
+    actions: Res<Actions>,
+    vehicle_query: Query<VehicleStateQuery, With<Vehicle>>,
+) {
+    if !actions.just_pressed(Action::Replay) {
+        return;
+    }
+
+    // Leaving the replay removes its vehicles and brings the live flock back as it was
+    if replay.active {
+        replay.active = false;
+        replay.playing = false;
+        replay.vehicles.clear();
+
+        vehicle_query.for_each(|(entity, ..)| commands.entity(entity).despawn());
+
+        let stash = std::mem::take(&mut replay.stash);
+        for state in stash.iter() {
+            state.respawn(&mut commands);
+        }
+
+        info!("Replay stopped, restored {} vehicles", stash.len());
+        return;
+    }
+
+    let path = config.path.with_extension(config.format.extension());
+
+    let frames = match load_trajectory(&path) {
+        Ok(frames) if !frames.is_empty() => frames,
+        Ok(_) => {
+            warn!("Recording {:?} contains no samples", path);
+            return;
+        }
+        Err(err) => {
+            error!("Failed to load recording {}", err);
+            return;
+        }
+    };
+
+    let stash = vehicle_query.iter().map(VehicleState::capture).collect();
+    vehicle_query.for_each(|(entity, ..)| commands.entity(entity).despawn());
+
+    let mut vehicles = HashMap::new();
+    for frame in frames.iter() {
+        for sample in frame.samples.iter() {
+            vehicles.entry(sample.id).or_insert_with(|| {
+                let entity = spawn_vehicle(
+                    &mut commands,
+                    &SpawnVehicle {
+                        position: sample.position,
+                        velocity: sample.velocity,
+                        profile: sample.profile,
+                        team: sample.team,
+                        genome: Genome::default(),
+                    },
+                );
+                commands
+                    .entity(entity)
+                    .insert(Name::new(format!("{}_{}", "Replay", sample.id)));
+                entity
+            });
+        }
+    }
+
+    info!(
+        "Replaying {} vehicles over {:.1}s from {:?}",
+        vehicles.len(),
+        frames.last().unwrap().time,
+        path
+    );
+
+    let speed = replay.speed;
+    *replay = Replay {
+        frames,
+        active: true,
+        playing: true,
+        time: 0.0,
+        speed,
+        vehicles,
+        stash,
+    };
+}
+
+fn replay_controls(mut replay: ResMut<Replay>, actions: Res<Actions>, time: Res<Time>) {
+    if actions.just_pressed(Action::ReplayPlay) {
+        // Restart when playing from the end
+        if !replay.playing && replay.time >= replay.duration() {
+            replay.time = 0.0;
+        }
+        replay.playing = !replay.playing;
+    }
+
+    if actions.just_pressed(Action::ReplayBack) {
+        let time = replay.time - REPLAY_SEEK_STEP;
+        replay.seek(time);
+    }
+
+    if actions.just_pressed(Action::ReplayForward) {
+        let time = replay.time + REPLAY_SEEK_STEP;
+        replay.seek(time);
+    }
+
+    if actions.just_pressed(Action::ReplaySlower) {
+        replay.speed = (replay.speed / 2.0).max(REPLAY_MIN_SPEED);
+    }
+
+    if actions.just_pressed(Action::ReplayFaster) {
+        replay.speed = (replay.speed * 2.0).min(REPLAY_MAX_SPEED);
+    }
+
+    if replay.playing {
+        let time = replay.time + time.delta_seconds() * replay.speed;
+        replay.seek(time);
+
+        if replay.time >= replay.duration() {
+            replay.playing = false;
+        }
+    }
+}
+
+fn drive_vehicles(
+    replay: Res<Replay>,
+    mut vehicle_query: Query<(&mut Transform, &mut Velocity, &mut Visibility), With<Vehicle>>,
+) {
+    if replay.frames.is_empty() {
+        return;
+    }
+
+    // Frames surrounding the replay time
+    let next = replay
+        .frames
+        .partition_point(|frame| frame.time <= replay.time)
+        .min(replay.frames.len() - 1);
+    let previous = next.saturating_sub(1);
+
+    let from = &replay.frames[previous];
+    let to = &replay.frames[next];
+
+    let span = to.time - from.time;
+    let t = if span > 0.0 {
+        ((replay.time - from.time) / span).clamp(0.0, 1.0)
+    } else {
+        1.0
+    };
+
+    let targets: HashMap<u64, &ReplaySample> = to
+        .samples
+        .iter()
+        .map(|sample| (sample.id, sample))
+        .collect();
+
+    vehicle_query.for_each_mut(|(_, _, mut visibility)| visibility.is_visible = false);
+
+    for sample in from.samples.iter() {
+        let entity = match replay.vehicles.get(&sample.id) {
+            Some(entity) => *entity,
+            None => continue,
+        };
+
+        if let Ok((mut transform, mut velocity, mut visibility)) = vehicle_query.get_mut(entity) {
+            let (position, current_velocity) = match targets.get(&sample.id) {
+                Some(target) => (
+                    sample.position.lerp(target.position, t),
+                    sample.velocity.lerp(target.velocity, t),
+                ),
+                None => (sample.position, sample.velocity),
+            };
+
+            transform.translation.x = position.x;
+            transform.translation.y = position.y;
+            transform.rotation =
+                Quat::from_rotation_z(current_velocity.y.atan2(current_velocity.x) - PI / 2.0);
+
+            velocity.0 = current_velocity;
+            visibility.is_visible = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_samples_are_grouped_by_tick() {
+        let contents = concat!(
+            "tick,time,id,x,y,vx,vy,ax,ay,team,profile\n",
+            "3,1.5,7,1,2,3,4,0,0,0,Primary\n",
+            "3,1.5,8,5,6,7,8,0,0,1,Predator\n",
+            "4,2,7,2,3,4,5,0,0,0,Primary\n",
+        );
+        let frames = parse_trajectory(Path::new("trajectory.csv"), contents).unwrap();
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].time, 0.0);
+        assert_eq!(frames[1].time, 0.5);
+        assert_eq!(frames[0].samples.len(), 2);
+        assert_eq!(frames[0].samples[1].id, 8);
+        assert_eq!(frames[0].samples[1].position, Vec2::new(5.0, 6.0));
+        assert_eq!(frames[0].samples[1].velocity, Vec2::new(7.0, 8.0));
+        assert_eq!(frames[0].samples[1].team, Team(1));
+        assert_eq!(frames[0].samples[1].profile, VehicleProfile::Predator);
+    }
+
+    #[test]
+    fn ndjson_matches_csv() {
+        let csv = "0,0.25,4294967297,1,2,3,4,0,0,3,Light\n1,0.5,4294967297,2,3,4,5,0,0,3,Light\n";
+        let ndjson = concat!(
+            "{\"tick\":0,\"time\":0.25,\"id\":4294967297,\"position\":[1,2],",
+            "\"velocity\":[3,4],\"acceleration\":[0,0],\"team\":3,\"profile\":\"Light\"}\n",
+            "{\"tick\":1,\"time\":0.5,\"id\":4294967297,\"position\":[2,3],",
+            "\"velocity\":[4,5],\"acceleration\":[0,0],\"team\":3,\"profile\":\"Light\"}\n",
+        );
+
+        let from_csv = parse_trajectory(Path::new("trajectory.csv"), csv).unwrap();
+        let from_ndjson = parse_trajectory(Path::new("trajectory.ndjson"), ndjson).unwrap();
+
+        assert_eq!(from_csv.len(), from_ndjson.len());
+        for (a, b) in from_csv.iter().zip(from_ndjson.iter()) {
+            assert_eq!(a.time, b.time);
+            for (a, b) in a.samples.iter().zip(b.samples.iter()) {
+                assert_eq!(a.id, b.id);
+                assert_eq!(a.position, b.position);
+                assert_eq!(a.velocity, b.velocity);
+                assert_eq!(a.team, b.team);
+                assert_eq!(a.profile, b.profile);
+            }
+        }
+    }
+
+    #[test]
+    fn older_recordings_replay_as_primary_on_the_first_team() {
+        let contents = "0,0,1,1,2,3,4,0,0\n";
+        let frames = parse_trajectory(Path::new("trajectory.csv"), contents).unwrap();
+
+        assert_eq!(frames[0].samples[0].team, Team::default());
+        assert_eq!(frames[0].samples[0].profile, VehicleProfile::Primary);
+    }
+
+    #[test]
+    fn malformed_samples_are_reported() {
+        let contents = "0,0,1,1,2,3,4,0,0\n1,0.1,1,oops,2,3,4,0,0\n";
+
+        let err = parse_trajectory(Path::new("trajectory.csv"), contents)
+            .err()
+            .unwrap();
+        assert!(err.ends_with(":2: malformed sample"), "{}", err);
+
+        let contents = "0,0,1,1,2,3,4,0,0,0,Giant\n";
+        assert!(parse_trajectory(Path::new("trajectory.csv"), contents).is_err());
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::{HashMap, VecDeque};
+
+use bevy::{ecs::query::ROQueryItem, prelude::*};
+
+use crate::{
+    actions::{update_actions, Action, Actions},
+    clock::{simulating, tick, SimClock},
+    energy::Energy,
+    hud::Hud,
+    leaders::Leader,
+    replay::not_replaying,
+    vehicle::{
+        spawn_vehicle, Acceleration, Genome, SpawnVehicle, Team, Vehicle, VehicleProfile,
+        VehicleSystem, Velocity, WanderTheta,
+    },
+};
+
+const REWIND_FAST_STEP: usize = 5;
+
+// How far back the history reaches in simulation seconds
+const REWIND_SECONDS: f32 = 10.0;
+
+// Upper bound on the recorded frames, which slow time scales would otherwise multiply
+const REWIND_MAX_FRAMES: usize = 600;
+
+pub struct RewindPlugin;
+
+pub type VehicleStateQuery = (
+    Entity,
+    &'static Transform,
+    &'static Velocity,
+    &'static WanderTheta,
+    &'static VehicleProfile,
+    &'static Team,
+    &'static Genome,
+    Option<&'static Energy>,
+    Option<&'static Leader>,
+);
+
+// A vehicle as it was at some point, with enough to spawn it again if it was removed since
+pub struct VehicleState {
+    pub entity: Entity,
+    translation: Vec3,
+    rotation: Quat,
+    velocity: Vec2,
+    wander_theta: f32,
+    profile: VehicleProfile,
+    team: Team,
+    genome: Genome,
+    energy: Option<f32>,
+    leader: bool,
+}
+
+impl VehicleState {
+    pub fn capture(
+        (entity, transform, velocity, wander_theta, profile, team, genome, energy, leader): ROQueryItem<
+            '_,
+            VehicleStateQuery,
+        >,
+    ) -> Self {
+        Self {
+            entity,
+            translation: transform.translation,
+            rotation: transform.rotation,
+            velocity: velocity.0,
+            wander_theta: wander_theta.0,
+            profile: *profile,
+            team: *team,
+            genome: *genome,
+            energy: energy.map(|energy| energy.0),
+            leader: leader.is_some(),
+        }
+    }
+
+    pub fn respawn(&self, commands: &mut Commands) -> Entity {
+        let entity = spawn_vehicle(
+            commands,
+            &SpawnVehicle {
+                position: self.translation.truncate(),
+                velocity: self.velocity,
+                profile: self.profile,
+                team: self.team,
+                genome: self.genome,
+            },
+        );
+
+        commands.entity(entity).insert((
+            Transform {
+                translation: self.translation,
+                rotation: self.rotation,
+                ..Default::default()
+            },
+            WanderTheta(self.wander_theta),
+        ));
+        if let Some(energy) = self.energy {
+            commands.entity(entity).insert(Energy(energy));
+        }
+        if self.leader {
+            commands.entity(entity).insert(Leader);
+        }
+
+        entity
+    }
+}
+
+struct RewindFrame {
+    time: f32,
+    vehicles: Vec<VehicleState>,
+}
+
+#[derive(Resource, Default)]
+pub struct Rewind {
+    frames: VecDeque<RewindFrame>,
+    // Frame currently shown while scrubbing
+    cursor: Option<usize>,
+}
+
+impl Rewind {
+    pub fn is_rewinding(&self) -> bool {
+        self.cursor.is_some()
+    }
+}
+
+impl Plugin for RewindPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Rewind>()
+            .add_system(
+                record_history
+                    .with_run_criteria(simulating)
+                    .after(VehicleSystem::Move),
+            )
+            // Scrubbing has to stop the simulation before the update stage decides whether to run
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                scrub
+                    .with_run_criteria(not_replaying)
+                    .after(tick)
+                    .after(update_actions),
+            )
+            .add_system(resume.before(VehicleSystem::Flock))
+            .add_system(status);
+    }
+}
+
+fn record_history(
+    mut rewind: ResMut<Rewind>,
+    clock: Res<SimClock>,
+    vehicle_query: Query<VehicleStateQuery, With<Vehicle>>,
+) {
+    let vehicles = vehicle_query.iter().map(VehicleState::capture).collect();
+
+    let time = clock.elapsed_seconds();
+    rewind.frames.push_back(RewindFrame { time, vehicles });
+
+    while rewind
+        .frames
+        .front()
+        .map_or(false, |frame| frame.time < time - REWIND_SECONDS)
+        || rewind.frames.len() > REWIND_MAX_FRAMES
+    {
+        rewind.frames.pop_front();
+    }
+}
+
+fn scrub(
+    mut commands: Commands,
+    mut rewind: ResMut<Rewind>,
+    mut clock: ResMut<SimClock>,
+    actions: Res<Actions>,
+    mut vehicle_query: Query<
+        (
+            Entity,
+            &mut Transform,
+            &mut Velocity,
+            &mut Acceleration,
+            &mut WanderTheta,
+            &mut Visibility,
+            Option<&mut Energy>,
+        ),
+        With<Vehicle>,
+    >,
+) {
+    if rewind.frames.is_empty() {
+        return;
+    }
+
+    let step = if actions.pressed(Action::RewindFast) {
+        REWIND_FAST_STEP
+    } else {
+        1
+    };
+    let latest = rewind.frames.len() - 1;
+
+    let cursor = if actions.pressed(Action::RewindBack) {
+        // Scrubbing freezes the simulation until it is resumed, starting with this frame
+        clock.freeze();
+        rewind.cursor.unwrap_or(latest).saturating_sub(step)
+    } else if actions.pressed(Action::RewindForward) {
+        match rewind.cursor {
+            Some(cursor) => (cursor + step).min(latest),
+            None => return,
+        }
+    } else {
+        return;
+    };
+
+    if rewind.cursor == Some(cursor) {
+        return;
+    }
+    rewind.cursor = Some(cursor);
+
+    // Vehicles spawned after this frame stay hidden until the simulation resumes, the ones
+    // removed since only come back then
+    vehicle_query.for_each_mut(|(_, _, _, _, _, mut visibility, _)| visibility.is_visible = false);
+
+    for state in rewind.frames[cursor].vehicles.iter() {
+        if let Ok((
+            _,
+            mut transform,
+            mut velocity,
+            mut acceleration,
+            mut wander_theta,
+            mut visibility,
+            energy,
+        )) = vehicle_query.get_mut(state.entity)
+        {
+            transform.translation = state.translation;
+            transform.rotation = state.rotation;
+            velocity.0 = state.velocity;
+            acceleration.0 = Vec2::ZERO;
+            wander_theta.0 = state.wander_theta;
+            visibility.is_visible = true;
+
+            if let (Some(mut energy), Some(recorded)) = (energy, state.energy) {
+                energy.0 = recorded;
+            }
+            if state.leader {
+                commands.entity(state.entity).insert(Leader);
+            } else {
+                commands.entity(state.entity).remove::<Leader>();
+            }
+        }
+    }
+}
+
+// Continuing from a rewound frame drops the history after it, as well as the
+// vehicles that did not exist yet at that point, and spawns the ones removed since again
+fn resume(
+    mut commands: Commands,
+    mut rewind: ResMut<Rewind>,
+    mut clock: ResMut<SimClock>,
+    vehicle_query: Query<(Entity, &Visibility), With<Vehicle>>,
+) {
+    let cursor = match rewind.cursor {
+        Some(cursor) if clock.is_running() => cursor,
+        _ => return,
+    };
+
+    rewind.frames.truncate(cursor + 1);
+    rewind.cursor = None;
+
+    vehicle_query
+        .iter()
+        .filter(|(_, visibility)| !visibility.is_visible)
+        .for_each(|(entity, _)| commands.entity(entity).despawn());
+
+    let mut respawned = HashMap::new();
+
+    for state in rewind.frames[cursor].vehicles.iter() {
+        if vehicle_query.contains(state.entity) {
+            continue;
+        }
+
+        respawned.insert(state.entity, state.respawn(&mut commands));
+    }
+
+    // The earlier history follows the respawned vehicles under their new ids
+    for state in rewind
+        .frames
+        .iter_mut()
+        .flat_map(|frame| frame.vehicles.iter_mut())
+    {
+        if let Some(entity) = respawned.get(&state.entity) {
+            state.entity = *entity;
+        }
+    }
+
+    clock.set_elapsed_seconds(rewind.frames[cursor].time);
+}
+
+fn status(rewind: Res<Rewind>, clock: Res<SimClock>, mut hud: ResMut<Hud>) {
+    match rewind.cursor {
+        Some(cursor) => hud.set(
+            "rewind",
+            format!(
+                "Rewind {:+.2}s",
+                rewind.frames[cursor].time - clock.elapsed_seconds()
+            ),
+        ),
+        None => hud.clear("rewind"),
+    }
+}
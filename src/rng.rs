@@ -0,0 +1,25 @@
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+#[derive(Resource, Deref, DerefMut)]
+pub struct SimRng(StdRng);
+
+impl Default for SimRng {
+    fn default() -> Self {
+        Self(StdRng::from_entropy())
+    }
+}
+
+impl SimRng {
+    // Restarts the generator from a fresh seed, so the sequence from here on
+    // can be reproduced by seeding with the returned value
+    pub fn reseed(&mut self) -> u64 {
+        let seed = self.0.gen();
+        self.seed(seed);
+        seed
+    }
+
+    pub fn seed(&mut self, seed: u64) {
+        self.0 = StdRng::seed_from_u64(seed);
+    }
+}
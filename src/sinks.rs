@@ -0,0 +1,217 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+
+use crate::{
+    actions::{Action, Actions},
+    clock::simulating,
+    cursor_world_position,
+    hud::Hud,
+    placeables::{hovered, scroll_placeables, PlaceableScroll},
+    replay::not_replaying,
+    vehicle::{DespawnSystem, Despawned, Vehicle},
+    MainCamera,
+};
+
+const SINK_RADIUS: f32 = 60.0;
+const SINK_MIN_RADIUS: f32 = 15.0;
+const SINK_MAX_RADIUS: f32 = 400.0;
+const SINK_SCROLL_STEP: f32 = 1.1;
+const SINK_COLOR: Color = Color::rgba(0.0, 0.0, 0.0, 0.35);
+const SINK_EDGE_COLOR: Color = Color::BLACK;
+const SINK_LINE_WIDTH: f32 = 2.0;
+const SINK_Z: f32 = 7.0;
+
+const ERASER_RADIUS: f32 = 40.0;
+
+pub struct SinkPlugin;
+
+// Region that despawns every vehicle entering it
+#[derive(Component, Reflect, FromReflect, Clone)]
+pub struct Sink {
+    pub radius: f32,
+    pub absorbed: usize,
+}
+
+#[derive(Resource, Default)]
+pub struct Removals {
+    pub absorbed: usize,
+    pub erased: usize,
+}
+
+impl Plugin for SinkPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Removals>()
+            .add_system(place_sinks)
+            .add_system(adjust_sinks.after(scroll_placeables))
+            .add_system(draw_sinks.after(adjust_sinks))
+            .add_system(removal_status)
+            // Vehicles are only despawned once the update stage is done, so no other
+            // system queues commands for them afterwards
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                erase
+                    .with_run_criteria(not_replaying)
+                    .label(DespawnSystem::Erase),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                absorb
+                    .with_run_criteria(simulating)
+                    .label(DespawnSystem::Absorb)
+                    .after(DespawnSystem::Erase),
+            );
+    }
+}
+
+fn sink_path(radius: f32) -> Path {
+    ShapePath::build_as(&shapes::Circle {
+        radius,
+        ..shapes::Circle::default()
+    })
+}
+
+pub fn spawn_sink(commands: &mut Commands, position: Vec2, radius: f32) -> Entity {
+    commands
+        .spawn(GeometryBuilder::build_as(
+            &shapes::Circle {
+                radius,
+                ..shapes::Circle::default()
+            },
+            DrawMode::Outlined {
+                fill_mode: FillMode::color(SINK_COLOR),
+                outline_mode: StrokeMode::new(SINK_EDGE_COLOR, SINK_LINE_WIDTH),
+            },
+            Transform::from_translation(position.extend(SINK_Z)),
+        ))
+        .insert(Name::new("Sink"))
+        .insert(Sink {
+            radius,
+            absorbed: 0,
+        })
+        .id()
+}
+
+// Places a sink under the cursor, or removes the one already there
+fn place_sinks(
+    mut commands: Commands,
+    sink_query: Query<(Entity, &Transform, &Sink)>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    windows: Res<Windows>,
+    actions: Res<Actions>,
+) {
+    if !actions.just_pressed(Action::PlaceSink) {
+        return;
+    }
+
+    let window = windows.get_primary().unwrap();
+    let (camera, camera_transform) = camera_query.single();
+
+    let world_pos = match cursor_world_position(window, camera, camera_transform) {
+        Some(world_pos) => world_pos,
+        None => return,
+    };
+
+    let sinks = sink_query
+        .iter()
+        .map(|(entity, transform, sink)| (entity, transform, sink.radius));
+
+    match hovered(world_pos, sinks) {
+        Some(entity) => commands.entity(entity).despawn(),
+        None => {
+            spawn_sink(&mut commands, world_pos, SINK_RADIUS);
+        }
+    }
+}
+
+fn adjust_sinks(mut sink_query: Query<&mut Sink>, scroll: Res<PlaceableScroll>) {
+    let (entity, scroll) = match scroll.0 {
+        Some(scroll) => scroll,
+        None => return,
+    };
+
+    if let Ok(mut sink) = sink_query.get_mut(entity) {
+        sink.radius =
+            (sink.radius * SINK_SCROLL_STEP.powf(scroll)).clamp(SINK_MIN_RADIUS, SINK_MAX_RADIUS);
+    }
+}
+
+fn draw_sinks(mut sink_query: Query<(&Sink, &mut Path), Changed<Sink>>) {
+    sink_query.for_each_mut(|(sink, mut path)| {
+        *path = sink_path(sink.radius);
+    });
+}
+
+fn absorb(
+    mut commands: Commands,
+    mut removals: ResMut<Removals>,
+    mut despawned: ResMut<Despawned>,
+    mut sink_query: Query<(&Transform, &mut Sink)>,
+    vehicle_query: Query<(Entity, &Transform), With<Vehicle>>,
+) {
+    for (entity, transform) in vehicle_query.iter() {
+        let position = transform.translation.truncate();
+
+        let sink = sink_query.iter_mut().find(|(sink_transform, sink)| {
+            sink_transform.translation.truncate().distance(position) <= sink.radius
+        });
+
+        if let Some((_, mut sink)) = sink {
+            if despawned.take(&mut commands, entity) {
+                sink.absorbed += 1;
+                removals.absorbed += 1;
+            }
+        }
+    }
+}
+
+fn erase(
+    mut commands: Commands,
+    mut removals: ResMut<Removals>,
+    mut despawned: ResMut<Despawned>,
+    vehicle_query: Query<(Entity, &Transform), With<Vehicle>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    windows: Res<Windows>,
+    actions: Res<Actions>,
+) {
+    if !actions.pressed(Action::Erase) {
+        return;
+    }
+
+    let window = windows.get_primary().unwrap();
+    let (camera, camera_transform) = camera_query.single();
+
+    let world_pos = match cursor_world_position(window, camera, camera_transform) {
+        Some(world_pos) => world_pos,
+        None => return,
+    };
+
+    for (entity, transform) in vehicle_query.iter() {
+        if transform.translation.truncate().distance(world_pos) <= ERASER_RADIUS
+            && despawned.take(&mut commands, entity)
+        {
+            removals.erased += 1;
+        }
+    }
+}
+
+fn removal_status(mut hud: ResMut<Hud>, removals: Res<Removals>, sink_query: Query<&Sink>) {
+    if removals.erased == 0 && removals.absorbed == 0 && sink_query.is_empty() {
+        hud.clear("removals");
+        return;
+    }
+
+    let sinks: Vec<String> = sink_query
+        .iter()
+        .map(|sink| sink.absorbed.to_string())
+        .collect();
+
+    hud.set(
+        "removals",
+        format!(
+            "Erased {}, absorbed {} (sinks {})",
+            removals.erased,
+            removals.absorbed,
+            sinks.join(", ")
+        ),
+    );
+}
@@ -0,0 +1,390 @@
+use std::{any::TypeId, collections::HashMap, fs, path::PathBuf};
+
+#[cfg(not(target_arch = "wasm32"))]
+use bevy::asset::FileAssetIo;
+use bevy::{prelude::*, reflect::GetTypeRegistration, scene::DynamicEntity};
+
+use crate::{
+    actions::{Action, Actions},
+    clock::SimClock,
+    emitters::{spawn_emitter, Emitter, EmitterPattern},
+    energy::{spawn_food, Food, Lifecycle},
+    formations::{spawn_formation, Formation, FormationMember, FormationShape},
+    leaders::Following,
+    rng::SimRng,
+    sinks::{spawn_sink, Sink},
+    target::{
+        spawn_target_field, Target, TargetField, TargetKind, TargetMode, TargetMotion, TargetStyle,
+    },
+    teams::{TeamRule, TeamRules, TEAM_COUNT},
+    vehicle::Vehicle,
+};
+
+// Relative to the asset folder, which is where the scene loader looks
+const SNAPSHOT_ASSET: &str = "snapshots/snapshot.scn.ron";
+
+// Scene id of the entity carrying `SnapshotState`, out of the way of vehicle ids
+const SNAPSHOT_STATE_ID: u32 = u32::MAX;
+
+pub struct SnapshotPlugin;
+
+// Simulation state that does not live on the vehicles themselves: the world objects
+// with their positions and the settings the simulation runs with
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct SnapshotState {
+    pub rng_seed: u64,
+    pub target: Vec2,
+    pub fields: Vec<(Vec2, TargetField)>,
+    pub sinks: Vec<(Vec2, Sink)>,
+    pub emitters: Vec<(Vec2, Emitter)>,
+    pub food: Vec<(Vec2, Food)>,
+    // Members are listed by their `SnapshotId`
+    pub formations: Vec<(Vec2, Formation, Vec<u32>)>,
+    pub clock_scale: f32,
+    pub target_style: String,
+    pub target_motion: TargetMotion,
+    pub team_rules: TeamRules,
+    pub following: Following,
+    pub lifecycle: Lifecycle,
+}
+
+// Scene id of a vehicle, for the formations it belongs to. Removed once loaded
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+struct SnapshotId(u32);
+
+// Components written for every vehicle in a snapshot
+#[derive(Resource, Default)]
+pub struct SnapshotComponents(Vec<TypeId>);
+
+#[derive(Resource, Default)]
+struct Snapshots {
+    handle: Option<Handle<DynamicScene>>,
+    pending: bool,
+}
+
+pub trait SnapshotApp {
+    fn snapshot_component<T: Component + Reflect + GetTypeRegistration>(&mut self) -> &mut Self;
+}
+
+impl SnapshotApp for App {
+    fn snapshot_component<T: Component + Reflect + GetTypeRegistration>(&mut self) -> &mut Self {
+        self.register_type::<T>();
+        self.world
+            .get_resource_or_insert_with(SnapshotComponents::default)
+            .0
+            .push(TypeId::of::<T>());
+        self
+    }
+}
+
+impl Plugin for SnapshotPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SnapshotComponents>()
+            .init_resource::<Snapshots>()
+            .snapshot_component::<SnapshotState>()
+            .register_type::<SnapshotId>()
+            // Read back by type name from the nested values of `SnapshotState`
+            .register_type::<Vec<(Vec2, TargetField)>>()
+            .register_type::<(Vec2, TargetField)>()
+            .register_type::<TargetField>()
+            .register_type::<TargetKind>()
+            .register_type::<Vec<(Vec2, Sink)>>()
+            .register_type::<(Vec2, Sink)>()
+            .register_type::<Sink>()
+            .register_type::<Vec<(Vec2, Emitter)>>()
+            .register_type::<(Vec2, Emitter)>()
+            .register_type::<Emitter>()
+            .register_type::<EmitterPattern>()
+            .register_type::<Vec<(Vec2, Food)>>()
+            .register_type::<(Vec2, Food)>()
+            .register_type::<Food>()
+            .register_type::<Vec<(Vec2, Formation, Vec<u32>)>>()
+            .register_type::<(Vec2, Formation, Vec<u32>)>()
+            .register_type::<Formation>()
+            .register_type::<FormationShape>()
+            .register_type::<Vec<u32>>()
+            .register_type::<TargetMotion>()
+            .register_type::<TargetMode>()
+            .register_type::<TeamRules>()
+            .register_type::<[[TeamRule; TEAM_COUNT]; TEAM_COUNT]>()
+            .register_type::<[TeamRule; TEAM_COUNT]>()
+            .register_type::<TeamRule>()
+            .register_type::<Following>()
+            .register_type::<[bool; TEAM_COUNT]>()
+            .register_type::<Lifecycle>()
+            .add_system(save_snapshot)
+            .add_system(load_snapshot)
+            .add_system(spawn_snapshot)
+            .add_system(apply_snapshot_state)
+            .add_system(apply_snapshot_settings);
+    }
+}
+
+// Where the asset server reads `SNAPSHOT_ASSET` from, so saving writes the file loading reads
+#[cfg(not(target_arch = "wasm32"))]
+fn snapshot_path(asset_server: &AssetServer) -> Option<PathBuf> {
+    asset_server
+        .asset_io()
+        .downcast_ref::<FileAssetIo>()
+        .map(|asset_io| asset_io.root_path().join(SNAPSHOT_ASSET))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn snapshot_path(_: &AssetServer) -> Option<PathBuf> {
+    None
+}
+
+fn placed<T: Component + Clone>(world: &mut World) -> Vec<(Vec2, T)> {
+    let mut query = world.query::<(&Transform, &T)>();
+    query
+        .iter(world)
+        .map(|(transform, object)| (transform.translation.truncate(), object.clone()))
+        .collect()
+}
+
+fn save_snapshot(world: &mut World) {
+    if !world
+        .resource::<Actions>()
+        .just_pressed(Action::SaveSnapshot)
+    {
+        return;
+    }
+
+    let rng_seed = world.resource_mut::<SimRng>().reseed();
+
+    let mut target_query = world.query_filtered::<&Transform, With<Target>>();
+    let target = target_query
+        .get_single(world)
+        .map(|transform| transform.translation.truncate())
+        .unwrap_or_default();
+
+    let state = SnapshotState {
+        rng_seed,
+        target,
+        fields: placed(world),
+        sinks: placed(world),
+        emitters: placed(world),
+        food: placed(world),
+        formations: placed::<Formation>(world)
+            .into_iter()
+            .map(|(position, formation)| {
+                let members = formation
+                    .members
+                    .iter()
+                    .map(|member| member.index())
+                    .collect();
+                (position, formation, members)
+            })
+            .collect(),
+        clock_scale: world.resource::<SimClock>().scale(),
+        target_style: world.resource::<TargetStyle>().name.to_string(),
+        target_motion: world.resource::<TargetMotion>().clone(),
+        team_rules: world.resource::<TeamRules>().clone(),
+        following: world.resource::<Following>().clone(),
+        lifecycle: world.resource::<Lifecycle>().clone(),
+    };
+
+    let mut vehicle_query = world.query_filtered::<Entity, With<Vehicle>>();
+    let type_registry = world.resource::<AppTypeRegistry>().clone();
+    let type_ids = world.resource::<SnapshotComponents>().0.clone();
+
+    let mut entities = Vec::new();
+    {
+        let world = &*world;
+        let registry = type_registry.read();
+
+        for entity in vehicle_query.iter(world) {
+            let mut components: Vec<Box<dyn Reflect>> = type_ids
+                .iter()
+                .filter_map(|type_id| registry.get(*type_id))
+                .filter_map(|registration| registration.data::<ReflectComponent>())
+                .filter_map(|reflect_component| reflect_component.reflect(world, entity))
+                .map(|component| component.clone_value())
+                .collect();
+            components.push(Box::new(SnapshotId(entity.index())));
+
+            entities.push(DynamicEntity {
+                entity: entity.index(),
+                components,
+            });
+        }
+    }
+
+    entities.push(DynamicEntity {
+        entity: SNAPSHOT_STATE_ID,
+        components: vec![Box::new(state) as Box<dyn Reflect>],
+    });
+
+    let vehicle_count = entities.len() - 1;
+    let scene = DynamicScene { entities };
+
+    let ron = match scene.serialize_ron(&type_registry) {
+        Ok(ron) => ron,
+        Err(err) => {
+            error!("Failed to serialize snapshot: {}", err);
+            return;
+        }
+    };
+
+    let path = match snapshot_path(world.resource::<AssetServer>()) {
+        Some(path) => path,
+        None => {
+            warn!("Snapshots can only be saved to the file system");
+            return;
+        }
+    };
+
+    let written = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&path, ron));
+
+    match written {
+        Ok(_) => info!("Saved {} vehicles to {:?}", vehicle_count, path),
+        Err(err) => error!("Failed to write snapshot {:?}: {}", path, err),
+    }
+}
+
+fn load_snapshot(
+    mut snapshots: ResMut<Snapshots>,
+    mut scene_spawner: ResMut<SceneSpawner>,
+    asset_server: Res<AssetServer>,
+    actions: Res<Actions>,
+) {
+    if !actions.just_pressed(Action::LoadSnapshot) {
+        return;
+    }
+
+    // Loading again replaces the vehicles of the previous load, and the file
+    // may have been overwritten since, so force the loader to read it again
+    match snapshots.handle.clone() {
+        Some(handle) => {
+            scene_spawner.despawn(handle);
+            asset_server.reload_asset(SNAPSHOT_ASSET);
+        }
+        None => snapshots.handle = Some(asset_server.load(SNAPSHOT_ASSET)),
+    }
+
+    snapshots.pending = true;
+}
+
+fn spawn_snapshot(
+    mut commands: Commands,
+    mut snapshots: ResMut<Snapshots>,
+    mut scene_spawner: ResMut<SceneSpawner>,
+    mut events: EventReader<AssetEvent<DynamicScene>>,
+    vehicle_query: Query<Entity, With<Vehicle>>,
+) {
+    for event in events.iter() {
+        let handle = match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => handle,
+            AssetEvent::Removed { .. } => continue,
+        };
+
+        if !snapshots.pending || snapshots.handle.as_ref() != Some(handle) {
+            continue;
+        }
+
+        vehicle_query.for_each(|entity| commands.entity(entity).despawn());
+        scene_spawner.spawn_dynamic(handle.clone());
+
+        snapshots.pending = false;
+        info!("Loaded snapshot {}", SNAPSHOT_ASSET);
+    }
+}
+
+// The world objects of the snapshot replace the placed ones, the vehicles of the same scene
+// are spawned along with the state so formations find their members right away
+fn apply_snapshot_state(
+    mut commands: Commands,
+    mut rng: ResMut<SimRng>,
+    state_query: Query<(Entity, &SnapshotState), Added<SnapshotState>>,
+    mut target_query: Query<&mut Transform, With<Target>>,
+    placed_query: Query<
+        Entity,
+        Or<(
+            With<TargetField>,
+            With<Sink>,
+            With<Emitter>,
+            With<Food>,
+            With<Formation>,
+        )>,
+    >,
+    id_query: Query<(Entity, &SnapshotId)>,
+) {
+    for (entity, state) in state_query.iter() {
+        rng.seed(state.rng_seed);
+
+        if let Ok(mut transform) = target_query.get_single_mut() {
+            transform.translation.x = state.target.x;
+            transform.translation.y = state.target.y;
+        }
+
+        placed_query.for_each(|placed| commands.entity(placed).despawn_recursive());
+
+        for (position, field) in state.fields.iter() {
+            spawn_target_field(&mut commands, *position, field.clone());
+        }
+        for (position, sink) in state.sinks.iter() {
+            let placed = spawn_sink(&mut commands, *position, sink.radius);
+            commands.entity(placed).insert(sink.clone());
+        }
+        for (position, emitter) in state.emitters.iter() {
+            spawn_emitter(&mut commands, *position, emitter.clone());
+        }
+        for (position, food) in state.food.iter() {
+            let placed = spawn_food(&mut commands, *position);
+            commands.entity(placed).insert(food.clone());
+        }
+
+        let vehicles: HashMap<u32, Entity> =
+            id_query.iter().map(|(entity, id)| (id.0, entity)).collect();
+
+        for (position, formation, members) in state.formations.iter() {
+            let members: Vec<Entity> = members
+                .iter()
+                .filter_map(|id| vehicles.get(id).copied())
+                .collect();
+
+            for member in members.iter() {
+                commands.entity(*member).insert(FormationMember);
+            }
+
+            let mut formation = formation.clone();
+            formation.members = members;
+            spawn_formation(&mut commands, *position, formation);
+        }
+
+        vehicles.values().for_each(|vehicle| {
+            commands.entity(*vehicle).remove::<SnapshotId>();
+        });
+
+        commands.entity(entity).despawn();
+    }
+}
+
+fn apply_snapshot_settings(
+    state_query: Query<&SnapshotState, Added<SnapshotState>>,
+    mut clock: ResMut<SimClock>,
+    mut style: ResMut<TargetStyle>,
+    mut motion: ResMut<TargetMotion>,
+    mut team_rules: ResMut<TeamRules>,
+    mut following: ResMut<Following>,
+    mut lifecycle: ResMut<Lifecycle>,
+) {
+    for state in state_query.iter() {
+        clock.set_scale(state.clock_scale);
+
+        if let Some(named) = TargetStyle::named(&state.target_style) {
+            *style = named;
+        }
+
+        // Applied rather than assigned, which leaves out what is not reflected, like open windows
+        motion.apply(&state.target_motion);
+        team_rules.apply(&state.team_rules);
+        following.apply(&state.following);
+        lifecycle.apply(&state.lifecycle);
+    }
+}
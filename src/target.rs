@@ -1,18 +1,312 @@
+use std::f32::consts::PI;
+
 use bevy::prelude::*;
 use bevy_prototype_lyon::prelude::*;
+use rand::prelude::*;
 
-use crate::MainCamera;
+use crate::{
+    actions::{Action, Actions},
+    clock::SimClock,
+    cursor_world_position,
+    hud::Hud,
+    placeables::{hovered, scroll_placeables, PlaceableScroll},
+    rng::SimRng,
+    vehicle::VehicleSystem,
+    world::WALL_MARGIN,
+    MainCamera,
+};
 
 pub const TARGET_RADIUS: f32 = 100.0;
 
+// Speed of the target when it moves on its own
+const TARGET_SPEED: f32 = 150.0;
+// Share of the play area covered by the scripted paths
+const TARGET_PATH_SCALE: f32 = 0.7;
+const TARGET_WANDER_TURN: f32 = PI;
+// Loop visited in waypoint mode, relative to the scripted path size
+const TARGET_WAYPOINTS: [[f32; 2]; 5] = [
+    [-1.0, -1.0],
+    [1.0, -1.0],
+    [0.3, 0.0],
+    [1.0, 1.0],
+    [-1.0, 1.0],
+];
+
+const TARGET_COLOR: Color = Color::RED;
+const TARGET_LINE_WIDTH: f32 = 3.0;
+// Extra outline width at the peak of a pulse
+const TARGET_PULSE_WIDTH: f32 = 6.0;
+// Pulses per second while engaged, idle targets pulse at half the rate
+const TARGET_PULSE_RATE: f32 = 1.0;
+// Degrees per second
+const TARGET_HUE_SPEED: f32 = 50.0;
+const TARGET_ZONE_ALPHA: f32 = 0.12;
+const TARGET_IDLE_ALPHA: f32 = 0.5;
+
+const TARGET_FIELD_RADIUS: f32 = 120.0;
+const TARGET_FIELD_MIN_RADIUS: f32 = 20.0;
+const TARGET_FIELD_MAX_RADIUS: f32 = 600.0;
+const TARGET_FIELD_STRENGTH: f32 = 2.0;
+const TARGET_FIELD_MIN_STRENGTH: f32 = 0.1;
+const TARGET_FIELD_MAX_STRENGTH: f32 = 10.0;
+const TARGET_FIELD_SCROLL_STEP: f32 = 1.1;
+const TARGET_FIELD_Z: f32 = 8.0;
+const TARGET_FIELD_LINE_WIDTH: f32 = 2.0;
+const TARGET_ATTRACT_COLOR: Color = Color::LIME_GREEN;
+const TARGET_REPEL_COLOR: Color = Color::RED;
+
 pub struct TargetPlugin;
 
+#[derive(Component, Default)]
+pub struct Target {
+    // Whether the vehicles are currently steering towards it
+    pub engaged: bool,
+}
+
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TargetSystem {
+    Move,
+}
+
+#[derive(Reflect, FromReflect, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TargetMode {
+    // Follows the cursor, engaged while the left mouse button is held
+    Cursor,
+    // Moved by a gamepad stick, engaged while the trigger is held
+    Gamepad,
+    // Moved to the cursor on click and always engaged
+    Place,
+    Circle,
+    FigureEight,
+    Waypoints,
+    RandomWalk,
+}
+
+impl TargetMode {
+    pub fn next(&self) -> Self {
+        match self {
+            TargetMode::Cursor => TargetMode::Gamepad,
+            TargetMode::Gamepad => TargetMode::Place,
+            TargetMode::Place => TargetMode::Circle,
+            TargetMode::Circle => TargetMode::FigureEight,
+            TargetMode::FigureEight => TargetMode::Waypoints,
+            TargetMode::Waypoints => TargetMode::RandomWalk,
+            TargetMode::RandomWalk => TargetMode::Cursor,
+        }
+    }
+
+    pub fn previous(&self) -> Self {
+        match self {
+            TargetMode::Cursor => TargetMode::RandomWalk,
+            TargetMode::Gamepad => TargetMode::Cursor,
+            TargetMode::Place => TargetMode::Gamepad,
+            TargetMode::Circle => TargetMode::Place,
+            TargetMode::FigureEight => TargetMode::Circle,
+            TargetMode::Waypoints => TargetMode::FigureEight,
+            TargetMode::RandomWalk => TargetMode::Waypoints,
+        }
+    }
+}
+
+#[derive(Resource, Reflect, Clone)]
+pub struct TargetMotion {
+    pub mode: TargetMode,
+    // Angle along the circle and figure-eight
+    phase: f32,
+    waypoint: usize,
+    heading: f32,
+}
+
+impl Default for TargetMotion {
+    fn default() -> Self {
+        Self {
+            mode: TargetMode::Cursor,
+            phase: 0.0,
+            waypoint: 0,
+            heading: 0.0,
+        }
+    }
+}
+
+impl TargetMotion {
+    // Next position of a target moving on its own within `bounds`
+    fn advance(&mut self, position: Vec2, bounds: Vec2, delta: f32, rng: &mut SimRng) -> Vec2 {
+        let size = bounds * TARGET_PATH_SCALE;
+
+        match self.mode {
+            TargetMode::Circle => {
+                let radius = size.min_element();
+                self.phase += delta * TARGET_SPEED / radius;
+                Vec2::new(self.phase.cos(), self.phase.sin()) * radius
+            }
+            TargetMode::FigureEight => {
+                self.phase += delta * TARGET_SPEED / size.min_element();
+                Vec2::new(
+                    size.x * self.phase.sin(),
+                    size.y * self.phase.sin() * self.phase.cos(),
+                )
+            }
+            TargetMode::Waypoints => {
+                let waypoint = Vec2::from(TARGET_WAYPOINTS[self.waypoint]) * size;
+                let offset = waypoint - position;
+                let step = TARGET_SPEED * delta;
+
+                if offset.length() <= step {
+                    self.waypoint = (self.waypoint + 1) % TARGET_WAYPOINTS.len();
+                    waypoint
+                } else {
+                    position + offset.normalize() * step
+                }
+            }
+            TargetMode::RandomWalk => {
+                // Scaled like a random walk so the path does not depend on the frame rate
+                let turn = TARGET_WANDER_TURN * delta.sqrt();
+                self.heading += rng.gen_range(-turn..=turn);
+
+                let next = position
+                    + Vec2::new(self.heading.cos(), self.heading.sin()) * TARGET_SPEED * delta;
+
+                // Bounce off the walls
+                if next.x.abs() > bounds.x {
+                    self.heading = PI - self.heading;
+                }
+                if next.y.abs() > bounds.y {
+                    self.heading = -self.heading;
+                }
+
+                next.clamp(-bounds, bounds)
+            }
+            TargetMode::Cursor | TargetMode::Gamepad | TargetMode::Place => position,
+        }
+    }
+}
+
+#[derive(Reflect, FromReflect, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TargetKind {
+    // Vehicles in range seek the center and slow down on arrival
+    Attract,
+    // Vehicles in range flee from the center
+    Repel,
+}
+
+// A placed target acting on every vehicle within its radius
+#[derive(Component, Reflect, FromReflect, Clone)]
+pub struct TargetField {
+    pub kind: TargetKind,
+    pub radius: f32,
+    pub strength: f32,
+}
+
+impl TargetField {
+    fn color(&self) -> Color {
+        match self.kind {
+            TargetKind::Attract => TARGET_ATTRACT_COLOR,
+            TargetKind::Repel => TARGET_REPEL_COLOR,
+        }
+    }
+}
+
+// Which parts of the target appearance are animated or shown
+#[derive(Resource, Clone, Copy)]
+pub struct TargetStyle {
+    pub name: &'static str,
+    pub pulse: bool,
+    pub color_cycle: bool,
+    // Inner ring on placed targets sized by their strength
+    pub strength_rings: bool,
+    // Fill the area in which vehicles slow down on arrival
+    pub arrival_zone: bool,
+}
+
+const TARGET_STYLES: [TargetStyle; 4] = [
+    TargetStyle {
+        name: "Plain",
+        pulse: false,
+        color_cycle: false,
+        strength_rings: false,
+        arrival_zone: false,
+    },
+    TargetStyle {
+        name: "Pulse",
+        pulse: true,
+        color_cycle: false,
+        strength_rings: true,
+        arrival_zone: true,
+    },
+    TargetStyle {
+        name: "Color cycle",
+        pulse: false,
+        color_cycle: true,
+        strength_rings: true,
+        arrival_zone: true,
+    },
+    TargetStyle {
+        name: "Pulse and color cycle",
+        pulse: true,
+        color_cycle: true,
+        strength_rings: true,
+        arrival_zone: true,
+    },
+];
+
+impl Default for TargetStyle {
+    fn default() -> Self {
+        TARGET_STYLES[1]
+    }
+}
+
+impl TargetStyle {
+    pub fn named(name: &str) -> Option<Self> {
+        TARGET_STYLES
+            .iter()
+            .find(|style| style.name == name)
+            .copied()
+    }
+
+    fn next(&self) -> Self {
+        let index = TARGET_STYLES
+            .iter()
+            .position(|style| style.name == self.name)
+            .unwrap_or(0);
+        TARGET_STYLES[(index + 1) % TARGET_STYLES.len()]
+    }
+
+    // Outline width of a target pulsing at `rate` pulses per second
+    fn line_width(&self, base: f32, rate: f32, time: f32) -> f32 {
+        if self.pulse {
+            base + (time * rate * PI).sin().abs() * TARGET_PULSE_WIDTH
+        } else {
+            base
+        }
+    }
+}
+
 #[derive(Component)]
-struct Target;
+struct StrengthRing;
 
 impl Plugin for TargetPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(crate_target).add_system(update);
+        app.init_resource::<TargetMotion>()
+            .add_startup_system(crate_target)
+            .add_system(target_controls)
+            .add_system(cursor_visibility.after(target_controls))
+            .add_system(
+                update
+                    .label(TargetSystem::Move)
+                    .after(target_controls)
+                    .before(VehicleSystem::Steer),
+            )
+            .add_system(place_targets)
+            .add_system(adjust_targets.after(scroll_placeables))
+            .init_resource::<TargetStyle>()
+            .add_system(style_controls)
+            .add_system(style_target.after(update).after(style_controls))
+            .add_system(draw_target_fields.after(adjust_targets))
+            .add_system(
+                style_target_fields
+                    .after(draw_target_fields)
+                    .after(style_controls),
+            );
     }
 }
 
@@ -27,48 +321,319 @@ fn crate_target(mut commands: Commands) {
             &shape,
             DrawMode::Outlined {
                 fill_mode: FillMode::color(Color::NONE),
-                outline_mode: StrokeMode::new(Color::RED, 3.0),
+                outline_mode: StrokeMode::new(TARGET_COLOR, TARGET_LINE_WIDTH),
             },
             Transform {
                 translation: Vec3::new(0.0, 0.0, 10.0),
                 ..Default::default()
             },
         ))
-        .insert(Target);
+        .insert(Target::default());
 }
 
 fn update(
-    mut target_query: Query<(&mut Transform, &mut DrawMode), With<Target>>,
+    mut target_query: Query<(&mut Transform, &mut Target)>,
+    mut motion: ResMut<TargetMotion>,
+    mut rng: ResMut<SimRng>,
     camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
     windows: Res<Windows>,
-    time: Res<Time>,
+    actions: Res<Actions>,
+    clock: Res<SimClock>,
 ) {
-    let (mut transform, mut draw_mode) = target_query.single_mut();
-    // let hue = (time.seconds_since_startup() * 50.0) % 360.0;
-    // let outline_width = 2.0 + time.seconds_since_startup().sin().abs() * 10.0;
-    let outline_width = 3.0;
+    let (mut transform, mut target) = target_query.single_mut();
 
     let window = windows.get_primary().unwrap();
     let (camera, camera_transform) = camera_query.single();
 
-    let window_size = Vec2::new(window.width() as f32, window.height() as f32);
+    let cursor = cursor_world_position(window, camera, camera_transform);
+    let bounds = Vec2::new(window.width(), window.height()) / 2.0 - WALL_MARGIN;
+    let position = transform.translation.truncate();
 
-    if let Some(_position) = window.cursor_position() {
-        let ndc = (_position / window_size) * 2.0 - Vec2::ONE;
-        let ndc_to_world = camera_transform.compute_matrix() * camera.projection_matrix().inverse();
-        let world_pos = ndc_to_world.project_point3(ndc.extend(-1.0));
-        let world_pos: Vec2 = world_pos.truncate();
+    let next = match motion.mode {
+        TargetMode::Cursor => {
+            target.engaged = cursor.is_some() && actions.pressed(Action::Attract);
+            cursor
+        }
+        // Driven by the gamepad plugin
+        TargetMode::Gamepad => None,
+        TargetMode::Place => {
+            target.engaged = true;
+            cursor.filter(|_| actions.just_pressed(Action::Attract))
+        }
+        _ => {
+            target.engaged = true;
+            Some(motion.advance(position, bounds, clock.delta_seconds(), &mut rng))
+        }
+    };
 
-        transform.translation.x = world_pos.x;
-        transform.translation.y = world_pos.y;
+    if let Some(next) = next {
+        transform.translation.x = next.x;
+        transform.translation.y = next.y;
     }
+}
 
-    // if let DrawMode::Outlined {
-    //     ref mut fill_mode,
-    //     ref mut outline_mode,
-    // } = *draw_mode
-    // {
-    //     fill_mode.color = Color::hsl(hue as f32, 1.0, 0.5);
-    //     outline_mode.options.line_width = outline_width as f32;
-    // }
+fn target_controls(mut motion: ResMut<TargetMotion>, mut hud: ResMut<Hud>, actions: Res<Actions>) {
+    if actions.just_pressed(Action::TargetMode) {
+        motion.mode = motion.mode.next();
+    }
+
+    if actions.just_pressed(Action::PreviousTargetMode) {
+        motion.mode = motion.mode.previous();
+    }
+
+    // The mode is also changed from other input devices
+    if motion.is_changed() {
+        match motion.mode {
+            TargetMode::Cursor => hud.clear("target"),
+            mode => hud.set("target", format!("Target {:?}", mode)),
+        }
+    }
+}
+
+// The target stands in for the cursor only while it follows it
+fn cursor_visibility(motion: Res<TargetMotion>, mut windows: ResMut<Windows>) {
+    if motion.is_changed() {
+        let window = windows.get_primary_mut().unwrap();
+        window.set_cursor_visibility(motion.mode != TargetMode::Cursor);
+    }
+}
+
+pub fn spawn_target_field(commands: &mut Commands, position: Vec2, field: TargetField) -> Entity {
+    let shape = shapes::Circle {
+        radius: field.radius,
+        ..shapes::Circle::default()
+    };
+
+    commands
+        .spawn(GeometryBuilder::build_as(
+            &shape,
+            DrawMode::Outlined {
+                fill_mode: FillMode::color(Color::NONE),
+                outline_mode: StrokeMode::new(field.color(), TARGET_FIELD_LINE_WIDTH),
+            },
+            Transform::from_translation(position.extend(TARGET_FIELD_Z)),
+        ))
+        .insert(Name::new(format!("{:?}", field.kind)))
+        .insert(field)
+        .with_children(|parent| {
+            parent
+                .spawn(GeometryBuilder::build_as(
+                    &shape,
+                    DrawMode::Stroke(StrokeMode::new(Color::NONE, 1.0)),
+                    Transform::from_xyz(0.0, 0.0, 0.1),
+                ))
+                .insert(StrengthRing);
+        })
+        .id()
+}
+
+fn place_targets(
+    mut commands: Commands,
+    field_query: Query<(Entity, &Transform, &TargetField)>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    windows: Res<Windows>,
+    actions: Res<Actions>,
+) {
+    let window = windows.get_primary().unwrap();
+    let (camera, camera_transform) = camera_query.single();
+
+    let world_pos = match cursor_world_position(window, camera, camera_transform) {
+        Some(world_pos) => world_pos,
+        None => return,
+    };
+
+    if actions.just_pressed(Action::PlaceTarget) {
+        let kind = if actions.pressed(Action::Repel) {
+            TargetKind::Repel
+        } else {
+            TargetKind::Attract
+        };
+
+        spawn_target_field(
+            &mut commands,
+            world_pos,
+            TargetField {
+                kind,
+                radius: TARGET_FIELD_RADIUS,
+                strength: TARGET_FIELD_STRENGTH,
+            },
+        );
+    }
+
+    if actions.just_pressed(Action::RemoveTarget) {
+        let fields = field_query
+            .iter()
+            .map(|(entity, transform, field)| (entity, transform, field.radius));
+
+        if let Some(entity) = hovered(world_pos, fields) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn adjust_targets(
+    mut field_query: Query<&mut TargetField>,
+    actions: Res<Actions>,
+    scroll: Res<PlaceableScroll>,
+) {
+    let (entity, scroll) = match scroll.0 {
+        Some(scroll) => scroll,
+        None => return,
+    };
+
+    if let Ok(mut field) = field_query.get_mut(entity) {
+        let factor = TARGET_FIELD_SCROLL_STEP.powf(scroll);
+
+        if actions.pressed(Action::Strength) {
+            field.strength = (field.strength * factor)
+                .clamp(TARGET_FIELD_MIN_STRENGTH, TARGET_FIELD_MAX_STRENGTH);
+        } else {
+            field.radius =
+                (field.radius * factor).clamp(TARGET_FIELD_MIN_RADIUS, TARGET_FIELD_MAX_RADIUS);
+        }
+    }
+}
+
+fn circle_path(radius: f32) -> Path {
+    ShapePath::build_as(&shapes::Circle {
+        radius,
+        ..shapes::Circle::default()
+    })
+}
+
+fn with_alpha(mut color: Color, alpha: f32) -> Color {
+    color.set_a(alpha);
+    color
+}
+
+fn draw_target_fields(
+    mut field_query: Query<
+        (&TargetField, &mut Path, &Children),
+        (Changed<TargetField>, Without<StrengthRing>),
+    >,
+    mut ring_query: Query<&mut Path, With<StrengthRing>>,
+) {
+    field_query.for_each_mut(|(field, mut path, children)| {
+        *path = circle_path(field.radius);
+
+        for child in children.iter() {
+            if let Ok(mut ring_path) = ring_query.get_mut(*child) {
+                *ring_path = circle_path(field.radius * field.strength / TARGET_FIELD_MAX_STRENGTH);
+            }
+        }
+    });
+}
+
+fn style_controls(mut style: ResMut<TargetStyle>, mut hud: ResMut<Hud>, actions: Res<Actions>) {
+    if actions.just_pressed(Action::TargetStyle) {
+        *style = style.next();
+        hud.set("target_style", format!("Target style {}", style.name));
+    }
+}
+
+fn style_target(
+    style: Res<TargetStyle>,
+    time: Res<Time>,
+    mut target_query: Query<(&Target, &mut DrawMode)>,
+) {
+    let (target, mut draw_mode) = target_query.single_mut();
+    let time = time.elapsed_seconds();
+
+    let color = if style.color_cycle {
+        Color::hsl((time * TARGET_HUE_SPEED) % 360.0, 1.0, 0.5)
+    } else {
+        TARGET_COLOR
+    };
+
+    let rate = if target.engaged {
+        TARGET_PULSE_RATE
+    } else {
+        TARGET_PULSE_RATE / 2.0
+    };
+
+    // Vehicles seeking the target slow down once they are inside the circle
+    let fill = if style.arrival_zone && target.engaged {
+        with_alpha(color, TARGET_ZONE_ALPHA)
+    } else {
+        Color::NONE
+    };
+
+    let outline = if target.engaged {
+        color
+    } else {
+        with_alpha(color, TARGET_IDLE_ALPHA)
+    };
+
+    set_outlined(
+        &mut draw_mode,
+        fill,
+        outline,
+        style.line_width(TARGET_LINE_WIDTH, rate, time),
+    );
+}
+
+fn style_target_fields(
+    style: Res<TargetStyle>,
+    time: Res<Time>,
+    mut field_query: Query<(&TargetField, &mut DrawMode, &Children), Without<StrengthRing>>,
+    mut ring_query: Query<(&mut DrawMode, &mut Visibility), With<StrengthRing>>,
+) {
+    let time = time.elapsed_seconds();
+
+    field_query.for_each_mut(|(field, mut draw_mode, children)| {
+        // Stronger fields are drawn more opaque and pulse faster
+        let alpha = (field.strength / TARGET_FIELD_MAX_STRENGTH).sqrt();
+        let rate = TARGET_PULSE_RATE * field.strength / TARGET_FIELD_STRENGTH;
+
+        set_outlined(
+            &mut draw_mode,
+            with_alpha(field.color(), alpha * 0.3),
+            with_alpha(field.color(), alpha.max(0.3)),
+            style.line_width(TARGET_FIELD_LINE_WIDTH, rate, time),
+        );
+
+        for child in children.iter() {
+            if let Ok((mut ring_mode, mut visibility)) = ring_query.get_mut(*child) {
+                if visibility.is_visible != style.strength_rings {
+                    visibility.is_visible = style.strength_rings;
+                }
+
+                let unchanged = matches!(
+                    &*ring_mode,
+                    DrawMode::Stroke(stroke) if stroke.color == field.color()
+                        && stroke.options.line_width == TARGET_FIELD_LINE_WIDTH
+                );
+                if !unchanged {
+                    *ring_mode =
+                        DrawMode::Stroke(StrokeMode::new(field.color(), TARGET_FIELD_LINE_WIDTH));
+                }
+            }
+        }
+    });
+}
+
+fn set_outlined(draw_mode: &mut Mut<DrawMode>, fill: Color, outline: Color, line_width: f32) {
+    if let DrawMode::Outlined {
+        fill_mode,
+        outline_mode,
+    } = &**draw_mode
+    {
+        // Like the vehicle colors, only written when they differ to avoid re-tessellating
+        if fill_mode.color == fill
+            && outline_mode.color == outline
+            && outline_mode.options.line_width == line_width
+        {
+            return;
+        }
+    }
+
+    if let DrawMode::Outlined {
+        fill_mode,
+        outline_mode,
+    } = &mut **draw_mode
+    {
+        fill_mode.color = fill;
+        outline_mode.color = outline;
+        outline_mode.options.line_width = line_width;
+    }
 }
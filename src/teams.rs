@@ -0,0 +1,209 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+
+use crate::{
+    actions::{Action, Actions},
+    clock::simulating,
+    formations::FormationMember,
+    vehicle::{
+        steer, Acceleration, Mass, Steering, SteeringForce, Team, Vehicle, VehicleSystem, Velocity,
+        VEHICLE_MAX_SPEED, VEHICLE_SIZE,
+    },
+};
+
+pub const TEAM_COUNT: usize = 4;
+const TEAM_COLORS: [Color; TEAM_COUNT] =
+    [Color::WHITE, Color::ORANGE_RED, Color::CYAN, Color::YELLOW];
+
+// How far vehicles notice the teams they avoid or chase
+const TEAM_PERCEPTION: f32 = VEHICLE_SIZE * 20.0;
+const TEAM_PERCEPTION_SQ: f32 = TEAM_PERCEPTION * TEAM_PERCEPTION;
+
+// Force factors
+const TEAM_AVOID_FACTOR: f32 = 1.5;
+const TEAM_CHASE_FACTOR: f32 = 1.2;
+
+pub struct TeamPlugin;
+
+// How vehicles of one team react to the vehicles of another
+#[derive(Reflect, FromReflect, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TeamRule {
+    Flock,
+    Ignore,
+    Avoid,
+    Chase,
+}
+
+impl TeamRule {
+    fn next(&self) -> Self {
+        match self {
+            TeamRule::Flock => TeamRule::Ignore,
+            TeamRule::Ignore => TeamRule::Avoid,
+            TeamRule::Avoid => TeamRule::Chase,
+            TeamRule::Chase => TeamRule::Flock,
+        }
+    }
+}
+
+// Rule matrix, indexed by the reacting team and then the team it reacts to.
+// Every team flocks with every other one unless told otherwise
+#[derive(Resource, Reflect, Clone)]
+pub struct TeamRules {
+    rules: [[TeamRule; TEAM_COUNT]; TEAM_COUNT],
+    #[reflect(ignore)]
+    open: bool,
+}
+
+impl Default for TeamRules {
+    fn default() -> Self {
+        Self {
+            rules: [[TeamRule::Flock; TEAM_COUNT]; TEAM_COUNT],
+            open: false,
+        }
+    }
+}
+
+impl TeamRules {
+    pub fn rule(&self, team: Team, other: Team) -> TeamRule {
+        self.rules[team.0 as usize % TEAM_COUNT][other.0 as usize % TEAM_COUNT]
+    }
+
+    fn reacts(&self) -> bool {
+        self.rules
+            .iter()
+            .flatten()
+            .any(|rule| matches!(rule, TeamRule::Avoid | TeamRule::Chase))
+    }
+}
+
+impl Plugin for TeamPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TeamRules>()
+            .add_system(toggle_team_rules)
+            .add_system(draw_team_rules.after(toggle_team_rules))
+            .add_system(
+                team_forces
+                    .with_run_criteria(simulating)
+                    .label(VehicleSystem::Steer)
+                    .after(VehicleSystem::Flock),
+            );
+    }
+}
+
+pub fn team_color(team: Team) -> Color {
+    TEAM_COLORS[team.0 as usize % TEAM_COUNT]
+}
+
+// Vehicles steer away from every vehicle they avoid and after the closest one they chase
+fn team_forces(
+    mut vehicle_query: Query<
+        (
+            &Transform,
+            &Velocity,
+            &Team,
+            &mut Acceleration,
+            &Mass,
+            &mut Steering,
+        ),
+        (With<Vehicle>, Without<FormationMember>),
+    >,
+    other_query: Query<(&Transform, &Team), With<Vehicle>>,
+    rules: Res<TeamRules>,
+) {
+    if !rules.reacts() {
+        return;
+    }
+
+    // Formation members only hold their slot, but are still seen by the others
+    let others: Vec<(Vec2, Team)> = other_query
+        .iter()
+        .map(|(transform, team)| (transform.translation.truncate(), *team))
+        .collect();
+
+    vehicle_query.for_each_mut(
+        |(transform, velocity, team, mut acceleration, mass, mut steering)| {
+            let position = transform.translation.truncate();
+            let mut chased: Option<(f32, Vec2)> = None;
+
+            for (other_position, other_team) in others.iter() {
+                let dist_sq = other_position.distance_squared(position);
+                if dist_sq > TEAM_PERCEPTION_SQ || dist_sq == 0.0 {
+                    continue;
+                }
+
+                match rules.rule(*team, *other_team) {
+                    TeamRule::Avoid => {
+                        let desired =
+                            (position - *other_position).normalize_or_zero() * VEHICLE_MAX_SPEED;
+
+                        // Closer vehicles are avoided harder
+                        let force = steer(desired, velocity.0)
+                            * TEAM_AVOID_FACTOR
+                            * (1.0 - dist_sq.sqrt() / TEAM_PERCEPTION);
+                        steering.record(SteeringForce::Evade, force);
+                        acceleration.apply_force(force, mass);
+                    }
+                    TeamRule::Chase => {
+                        if chased.map_or(true, |(closest, _)| dist_sq < closest) {
+                            chased = Some((dist_sq, *other_position));
+                        }
+                    }
+                    TeamRule::Flock | TeamRule::Ignore => {}
+                }
+            }
+
+            if let Some((_, other_position)) = chased {
+                let desired = (other_position - position).normalize_or_zero() * VEHICLE_MAX_SPEED;
+
+                let force = steer(desired, velocity.0) * TEAM_CHASE_FACTOR;
+                steering.record(SteeringForce::Pursue, force);
+                acceleration.apply_force(force, mass);
+            }
+        },
+    );
+}
+
+fn toggle_team_rules(mut rules: ResMut<TeamRules>, actions: Res<Actions>) {
+    if actions.just_pressed(Action::TeamRules) {
+        rules.open = !rules.open;
+    }
+}
+
+// Each row is how that team reacts to the team of each column, clicking a rule cycles it
+fn draw_team_rules(mut egui_context: ResMut<EguiContext>, mut rules: ResMut<TeamRules>) {
+    if !rules.open {
+        return;
+    }
+
+    let mut open = true;
+
+    egui::Window::new("Team rules")
+        .open(&mut open)
+        .show(egui_context.ctx_mut(), |ui| {
+            egui::Grid::new("team_rules").striped(true).show(ui, |ui| {
+                ui.label("");
+                for other in 0..TEAM_COUNT {
+                    ui.label(format!("Team {}", other));
+                }
+                ui.end_row();
+
+                for (team, row) in rules.rules.iter_mut().enumerate() {
+                    ui.label(format!("Team {}", team));
+
+                    for rule in row.iter_mut() {
+                        if ui.button(format!("{:?}", rule)).clicked() {
+                            *rule = rule.next();
+                        }
+                    }
+
+                    ui.end_row();
+                }
+            });
+
+            if ui.button("Defaults").clicked() {
+                rules.rules = TeamRules::default().rules;
+            }
+        });
+
+    rules.open = open;
+}
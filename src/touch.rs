@@ -0,0 +1,225 @@
+#[cfg(target_arch = "wasm32")]
+use std::sync::{Arc, Mutex};
+
+#[cfg(target_arch = "wasm32")]
+use bevy::input::{
+    touch::{TouchInput, TouchPhase},
+    InputSystem,
+};
+use bevy::{input::touch::Touches, prelude::*};
+
+use crate::{
+    brush::{paint, TouchBrush},
+    screen_to_world,
+    target::{Target, TargetSystem},
+    vehicle::VehicleSystem,
+    MainCamera,
+};
+
+// How long a finger has to rest before it starts spawning vehicles
+const TOUCH_LONG_PRESS_SECONDS: f32 = 0.5;
+// How far a resting finger may drift, in logical pixels
+const TOUCH_LONG_PRESS_SLOP: f32 = 10.0;
+
+const TOUCH_MIN_ZOOM: f32 = 0.25;
+const TOUCH_MAX_ZOOM: f32 = 4.0;
+
+pub struct TouchPlugin;
+
+#[derive(Resource, Default)]
+struct LongPress {
+    // The single finger currently touching, with where and since when it rests
+    touch: Option<(u64, Vec2, f32)>,
+    spawning: bool,
+}
+
+impl Plugin for TouchPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LongPress>()
+            .add_system(
+                drag_target
+                    .after(TargetSystem::Move)
+                    .before(VehicleSystem::Steer)
+                    .before(paint),
+            )
+            .add_system(pinch_camera);
+
+        #[cfg(target_arch = "wasm32")]
+        app.init_resource::<WebTouches>()
+            .add_startup_system(listen_web_touches)
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                forward_web_touches.before(InputSystem),
+            );
+    }
+}
+
+// Touches read from the canvas, winit only reports them as mouse input in the browser
+#[cfg(target_arch = "wasm32")]
+#[derive(Resource, Default)]
+struct WebTouches(Arc<Mutex<Vec<TouchInput>>>);
+
+#[cfg(target_arch = "wasm32")]
+fn listen_web_touches(web_touches: Res<WebTouches>) {
+    use wasm_bindgen::{closure::Closure, JsCast};
+
+    let canvas = match web_sys::window()
+        .and_then(|window| window.document())
+        .and_then(|document| document.get_element_by_id("bevy"))
+    {
+        Some(canvas) => canvas,
+        None => {
+            warn!("No #bevy canvas to read touches from");
+            return;
+        }
+    };
+
+    for (name, phase) in [
+        ("touchstart", TouchPhase::Started),
+        ("touchmove", TouchPhase::Moved),
+        ("touchend", TouchPhase::Ended),
+        ("touchcancel", TouchPhase::Cancelled),
+    ] {
+        let touches = web_touches.0.clone();
+        let target = canvas.clone();
+
+        let listener = Closure::wrap(Box::new(move |event: web_sys::TouchEvent| {
+            event.prevent_default();
+
+            // Logical pixels from the top left corner of the canvas, like winit reports them
+            let rect = target.get_bounding_client_rect();
+            let changed = event.changed_touches();
+            let mut touches = touches.lock().unwrap();
+
+            for touch in (0..changed.length()).filter_map(|index| changed.get(index)) {
+                touches.push(TouchInput {
+                    phase,
+                    position: Vec2::new(
+                        (touch.client_x() as f64 - rect.left()) as f32,
+                        (touch.client_y() as f64 - rect.top()) as f32,
+                    ),
+                    force: None,
+                    id: touch.identifier() as u64,
+                });
+            }
+        }) as Box<dyn FnMut(web_sys::TouchEvent)>);
+
+        if let Err(err) =
+            canvas.add_event_listener_with_callback(name, listener.as_ref().unchecked_ref())
+        {
+            error!("Failed to listen to {}: {:?}", name, err);
+        }
+
+        // The listener stays for the lifetime of the page
+        listener.forget();
+    }
+}
+
+// Hands the canvas touches to bevy before it updates `Touches`
+#[cfg(target_arch = "wasm32")]
+fn forward_web_touches(web_touches: Res<WebTouches>, mut touch_events: EventWriter<TouchInput>) {
+    touch_events.send_batch(web_touches.0.lock().unwrap().drain(..));
+}
+
+// Touch positions are measured from the top left corner, unlike the cursor
+fn touch_world_position(
+    window: &Window,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    position: Vec2,
+) -> Vec2 {
+    screen_to_world(
+        window,
+        camera,
+        camera_transform,
+        Vec2::new(position.x, window.height() - position.y),
+    )
+}
+
+fn drag_target(
+    mut long_press: ResMut<LongPress>,
+    mut touch_brush: ResMut<TouchBrush>,
+    mut target_query: Query<(&mut Transform, &mut Target)>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    touches: Res<Touches>,
+    windows: Res<Windows>,
+    time: Res<Time>,
+) {
+    touch_brush.0 = None;
+
+    let mut pressed = touches.iter();
+
+    let touch = match (pressed.next(), pressed.next()) {
+        (Some(touch), None) => touch,
+        _ => {
+            *long_press = LongPress::default();
+            return;
+        }
+    };
+
+    let now = time.elapsed_seconds();
+    let rested = match long_press.touch {
+        Some((id, anchor, since))
+            if id == touch.id() && touch.position().distance(anchor) <= TOUCH_LONG_PRESS_SLOP =>
+        {
+            now - since
+        }
+        _ => {
+            long_press.touch = Some((touch.id(), touch.position(), now));
+            long_press.spawning = false;
+            0.0
+        }
+    };
+
+    if rested >= TOUCH_LONG_PRESS_SECONDS {
+        long_press.spawning = true;
+    }
+
+    let window = windows.get_primary().unwrap();
+    let (camera, camera_transform) = camera_query.single();
+    let world_pos = touch_world_position(window, camera, camera_transform, touch.position());
+
+    // A resting finger paints with the spawn brush like holding space, otherwise it drags the
+    // target
+    if long_press.spawning {
+        touch_brush.0 = Some(world_pos);
+        return;
+    }
+
+    let (mut transform, mut target) = target_query.single_mut();
+    transform.translation.x = world_pos.x;
+    transform.translation.y = world_pos.y;
+    target.engaged = true;
+}
+
+fn pinch_camera(
+    mut camera_query: Query<(&mut Transform, &mut OrthographicProjection), With<MainCamera>>,
+    touches: Res<Touches>,
+) {
+    let mut pressed = touches.iter();
+
+    let (first, second) = match (pressed.next(), pressed.next(), pressed.next()) {
+        (Some(first), Some(second), None) => (first, second),
+        _ => return,
+    };
+
+    let (mut transform, mut projection) = camera_query.single_mut();
+
+    let distance = first.position().distance(second.position());
+    let previous_distance = first
+        .previous_position()
+        .distance(second.previous_position());
+
+    if distance > 0.0 && previous_distance > 0.0 {
+        projection.scale =
+            (projection.scale * previous_distance / distance).clamp(TOUCH_MIN_ZOOM, TOUCH_MAX_ZOOM);
+    }
+
+    // Moving both fingers drags the view along, y points down on the screen
+    let midpoint = (first.position() + second.position()) / 2.0;
+    let previous_midpoint = (first.previous_position() + second.previous_position()) / 2.0;
+    let delta = (midpoint - previous_midpoint) * projection.scale;
+
+    transform.translation.x -= delta.x;
+    transform.translation.y += delta.y;
+}
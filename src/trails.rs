@@ -0,0 +1,211 @@
+use std::collections::VecDeque;
+
+use bevy::{
+    prelude::*,
+    render::{mesh::PrimitiveTopology, view::NoFrustumCulling},
+    sprite::Mesh2dHandle,
+};
+
+use crate::{
+    actions::{Action, Actions},
+    closest_within, cursor_world_position,
+    hud::Hud,
+    rewind::Rewind,
+    MainCamera,
+};
+
+const TRAIL_LENGTH: usize = 60;
+const TRAIL_COLOR: Color = Color::rgba(1.0, 0.75, 0.8, 0.6);
+const TRAIL_SELECT_RADIUS: f32 = 20.0;
+const TRAIL_Z: f32 = 800.0;
+
+pub struct TrailPlugin;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrailMode {
+    Off,
+    All,
+    // Only vehicles with a selected trail
+    Selected,
+}
+
+impl TrailMode {
+    fn next(self) -> Self {
+        match self {
+            TrailMode::Off => TrailMode::All,
+            TrailMode::All => TrailMode::Selected,
+            TrailMode::Selected => TrailMode::Off,
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct TrailSettings {
+    pub mode: TrailMode,
+}
+
+impl Default for TrailSettings {
+    fn default() -> Self {
+        Self {
+            mode: TrailMode::Off,
+        }
+    }
+}
+
+// Ring buffer of the most recent positions of a vehicle
+#[derive(Component, Default)]
+pub struct Trail {
+    points: VecDeque<Vec2>,
+    pub selected: bool,
+}
+
+#[derive(Component)]
+struct TrailMesh;
+
+impl Plugin for TrailPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TrailSettings>()
+            .add_startup_system(create_trail_mesh)
+            .add_system(trail_controls)
+            .add_system(sample_trails)
+            .add_system(draw_trails.after(sample_trails));
+    }
+}
+
+fn create_trail_mesh(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let mut mesh = Mesh::new(PrimitiveTopology::LineList);
+    set_trail_vertices(&mut mesh, Vec::new(), Vec::new());
+
+    commands
+        .spawn(ColorMesh2dBundle {
+            mesh: meshes.add(mesh).into(),
+            material: materials.add(ColorMaterial::from(Color::WHITE)),
+            transform: Transform::from_xyz(0.0, 0.0, TRAIL_Z),
+            visibility: Visibility { is_visible: false },
+            ..default()
+        })
+        // The mesh is rebuilt every frame, so its initial bounds mean nothing
+        .insert(NoFrustumCulling)
+        .insert(TrailMesh)
+        .insert(Name::new("Trails"));
+}
+
+fn set_trail_vertices(mesh: &mut Mesh, mut positions: Vec<[f32; 3]>, mut colors: Vec<[f32; 4]>) {
+    // Keep a degenerate segment around so the vertex buffer is never empty
+    if positions.is_empty() {
+        positions = vec![[0.0; 3]; 2];
+        colors = vec![[0.0; 4]; 2];
+    }
+
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+}
+
+fn trail_controls(
+    mut settings: ResMut<TrailSettings>,
+    mut hud: ResMut<Hud>,
+    mut trail_query: Query<(&Transform, &mut Trail)>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    windows: Res<Windows>,
+    actions: Res<Actions>,
+) {
+    if actions.just_pressed(Action::TrailMode) {
+        settings.mode = settings.mode.next();
+
+        match settings.mode {
+            TrailMode::Off => hud.clear("trails"),
+            mode => hud.set("trails", format!("Trails {:?}", mode)),
+        }
+    }
+
+    if !actions.just_pressed(Action::TrailSelect) {
+        return;
+    }
+
+    let window = windows.get_primary().unwrap();
+    let (camera, camera_transform) = camera_query.single();
+
+    let world_pos = match cursor_world_position(window, camera, camera_transform) {
+        Some(world_pos) => world_pos,
+        None => return,
+    };
+
+    // Toggle the trail of the vehicle closest to the cursor
+    let closest = closest_within(
+        trail_query
+            .iter_mut()
+            .map(|(transform, trail)| (transform.translation.truncate(), trail)),
+        world_pos,
+        TRAIL_SELECT_RADIUS,
+    );
+
+    if let Some(mut trail) = closest {
+        trail.selected = !trail.selected;
+    }
+}
+
+fn sample_trails(
+    settings: Res<TrailSettings>,
+    rewind: Res<Rewind>,
+    mut trail_query: Query<(&Transform, &mut Trail), Changed<Transform>>,
+) {
+    // Scrubbing jumps around in time, which would only leave streaks behind
+    if settings.mode == TrailMode::Off || rewind.is_rewinding() {
+        return;
+    }
+
+    trail_query.for_each_mut(|(transform, mut trail)| {
+        if trail.points.len() == TRAIL_LENGTH {
+            trail.points.pop_front();
+        }
+        trail.points.push_back(transform.translation.truncate());
+    });
+}
+
+fn draw_trails(
+    settings: Res<TrailSettings>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut mesh_query: Query<(&Mesh2dHandle, &mut Visibility), With<TrailMesh>>,
+    trail_query: Query<&Trail>,
+) {
+    let (handle, mut visibility) = mesh_query.single_mut();
+
+    visibility.is_visible = settings.mode != TrailMode::Off;
+    if !visibility.is_visible {
+        return;
+    }
+
+    let [r, g, b, a] = TRAIL_COLOR.as_linear_rgba_f32();
+    let mut positions = Vec::new();
+    let mut colors = Vec::new();
+
+    for trail in trail_query.iter() {
+        if settings.mode == TrailMode::Selected && !trail.selected {
+            continue;
+        }
+
+        let len = trail.points.len();
+        let fade = |index: usize| [r, g, b, a * index as f32 / len as f32];
+
+        // Segments fade out towards the oldest position
+        for (i, (from, to)) in trail
+            .points
+            .iter()
+            .zip(trail.points.iter().skip(1))
+            .enumerate()
+        {
+            positions.push([from.x, from.y, 0.0]);
+            positions.push([to.x, to.y, 0.0]);
+            colors.push(fade(i));
+            colors.push(fade(i + 1));
+        }
+    }
+
+    if let Some(mesh) = meshes.get_mut(&handle.0) {
+        set_trail_vertices(mesh, positions, colors);
+    }
+}
@@ -1,18 +1,30 @@
-use std::f32::consts::PI;
+use std::{collections::HashSet, f32::consts::PI};
 
 use bevy::prelude::*;
 use bevy_prototype_lyon::prelude::*;
 
 use rand::prelude::*;
 
-use crate::{target::TARGET_RADIUS, world::WALL_MARGIN, MainCamera};
+use crate::{
+    clock::{simulating, SimClock},
+    coloring::Tint,
+    energy::{Energy, ENERGY_START},
+    formations::FormationMember,
+    rng::SimRng,
+    snapshot::SnapshotApp,
+    target::{Target, TargetField, TargetKind, TARGET_RADIUS},
+    teams::{TeamRule, TeamRules},
+    trails::Trail,
+    world::WALL_MARGIN,
+};
 
 const VEHICLE_COUNT: usize = 100;
 
-const VEHICLE_SIZE: f32 = 4.0;
-const VEHICLE_MAX_SPEED: f32 = 300.0;
+pub const VEHICLE_SIZE: f32 = 4.0;
+pub const VEHICLE_MAX_SPEED: f32 = 300.0;
+const VEHICLE_Z: f32 = 900.0;
 const VEHICLE_MAX_SPEED_VEC: Vec2 = Vec2::from_array([VEHICLE_MAX_SPEED; 2]);
-const VEHICLE_MAX_FORCE: Vec2 = Vec2::from_array([60.0; 2]);
+pub const VEHICLE_MAX_FORCE: Vec2 = Vec2::from_array([60.0; 2]);
 const VEHICLE_MASS: f32 = 10.0;
 const VEHICLE_BODY_COLOR: Color = Color::WHITE;
 const VEHICLE_EDGE_COLOR: Color = Color::PINK;
@@ -23,16 +35,16 @@ const VEHICLE_VIEW_ANGLE: f32 = PI / 8.0;
 
 const VEHICLE_SECONDARY_BODY_COLOR: Color = Color::WHITE;
 const VEHICLE_SECONDARY_EDGE_COLOR: Color = Color::ORANGE_RED;
+const VEHICLE_LIGHT_EDGE_COLOR: Color = Color::CYAN;
+const VEHICLE_HEAVY_EDGE_COLOR: Color = Color::PURPLE;
+const VEHICLE_PREDATOR_BODY_COLOR: Color = Color::BLACK;
+const VEHICLE_PREDATOR_EDGE_COLOR: Color = Color::RED;
 
 // Distances
 const VEHICLE_SEPERATION_DIST: f32 = VEHICLE_SIZE * 4.0;
 const VEHICLE_ALIGN_DIST: f32 = VEHICLE_SIZE * 12.0;
 const VEHICLE_COHESION_DIST: f32 = VEHICLE_SIZE * 10.0;
 const VEHICLE_VIEW_DIST: f32 = VEHICLE_SIZE * 4.0;
-const VEHICLE_SEPERATION_DIST_SQ: f32 = VEHICLE_SEPERATION_DIST * VEHICLE_SEPERATION_DIST;
-const VEHICLE_ALIGN_DIST_SQ: f32 = VEHICLE_ALIGN_DIST * VEHICLE_ALIGN_DIST;
-const VEHICLE_COHESION_DIST_SQ: f32 = VEHICLE_COHESION_DIST * VEHICLE_COHESION_DIST;
-const VEHICLE_VIEW_DIST_SQ: f32 = VEHICLE_VIEW_DIST * VEHICLE_VIEW_DIST;
 
 // Force factors
 const VEHICLE_SEPERATION_FACTOR: f32 = 1.6;
@@ -43,140 +55,473 @@ const VEHICLE_TARGET_FACTOR: f32 = 2.0;
 const VEHICLE_WANDER_FACTOR: f32 = 0.8;
 const VEHICLE_VIEW_FACTOR: f32 = 1.2;
 
-const LINE_WIDTH: f32 = 2.0;
+pub const LINE_WIDTH: f32 = 2.0;
 
 pub struct VehiclePlugin;
 
-#[derive(Component)]
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
 pub struct Vehicle;
 
-#[derive(Component, Deref, DerefMut)]
-struct Velocity(Vec2);
+#[derive(Component, Reflect, Default, Deref, DerefMut)]
+#[reflect(Component)]
+pub struct Velocity(pub Vec2);
 
-#[derive(Component, Deref, DerefMut)]
-struct Acceleration(Vec2);
+#[derive(Component, Reflect, Default, Deref, DerefMut)]
+#[reflect(Component)]
+pub struct Acceleration(pub Vec2);
 
-#[derive(Component)]
-struct Mass(f32);
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Mass(pub f32);
 
-#[derive(Component)]
-struct WanderTheta(f32);
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct WanderTheta(pub f32);
+
+// Colors the vehicle is drawn with when not colored by its state
+#[derive(Component, Reflect, Clone, Copy)]
+#[reflect(Component)]
+pub struct VehicleColors {
+    pub body: Color,
+    pub edge: Color,
+}
+
+impl Default for VehicleColors {
+    fn default() -> Self {
+        Self {
+            body: VEHICLE_BODY_COLOR,
+            edge: VEHICLE_EDGE_COLOR,
+        }
+    }
+}
+
+impl VehicleColors {
+    // Colors of vehicles spawned by the user
+    pub fn secondary() -> Self {
+        Self {
+            body: VEHICLE_SECONDARY_BODY_COLOR,
+            edge: VEHICLE_SECONDARY_EDGE_COLOR,
+        }
+    }
+}
+
+// Group a vehicle belongs to
+#[derive(Component, Reflect, FromReflect, Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[reflect(Component)]
+pub struct Team(pub u8);
+
+// Steering weights and perception radii, inherited with mutation by offspring
+#[derive(Component, Reflect, Clone, Copy, Debug)]
+#[reflect(Component)]
+pub struct Genome {
+    pub separation: f32,
+    pub alignment: f32,
+    pub cohesion: f32,
+    pub view: f32,
+    pub wander: f32,
+    pub separation_dist: f32,
+    pub align_dist: f32,
+    pub cohesion_dist: f32,
+    pub view_dist: f32,
+    // Ancestors since the vehicle that was spawned without a parent
+    pub generation: u32,
+}
+
+impl Default for Genome {
+    fn default() -> Self {
+        Self {
+            separation: VEHICLE_SEPERATION_FACTOR,
+            alignment: VEHICLE_ALIGN_FACTOR,
+            cohesion: VEHICLE_COHESION_FACTOR,
+            view: VEHICLE_VIEW_FACTOR,
+            wander: VEHICLE_WANDER_FACTOR,
+            separation_dist: VEHICLE_SEPERATION_DIST,
+            align_dist: VEHICLE_ALIGN_DIST,
+            cohesion_dist: VEHICLE_COHESION_DIST,
+            view_dist: VEHICLE_VIEW_DIST,
+            generation: 0,
+        }
+    }
+}
+
+// Presets for the look and mass of a vehicle
+#[derive(Component, Reflect, FromReflect, Clone, Copy, Debug, PartialEq, Eq)]
+#[reflect(Component)]
+pub enum VehicleProfile {
+    Primary,
+    Secondary,
+    // Half the mass, so steering forces turn it faster
+    Light,
+    Heavy,
+    // Hunts the vehicles of every other profile
+    Predator,
+}
+
+impl Default for VehicleProfile {
+    fn default() -> Self {
+        VehicleProfile::Primary
+    }
+}
+
+impl VehicleProfile {
+    pub fn next(&self) -> Self {
+        match self {
+            VehicleProfile::Primary => VehicleProfile::Secondary,
+            VehicleProfile::Secondary => VehicleProfile::Light,
+            VehicleProfile::Light => VehicleProfile::Heavy,
+            VehicleProfile::Heavy => VehicleProfile::Predator,
+            VehicleProfile::Predator => VehicleProfile::Primary,
+        }
+    }
+
+    pub fn colors(&self) -> VehicleColors {
+        match self {
+            VehicleProfile::Primary => VehicleColors::default(),
+            VehicleProfile::Secondary => VehicleColors::secondary(),
+            VehicleProfile::Light => VehicleColors {
+                body: VEHICLE_SECONDARY_BODY_COLOR,
+                edge: VEHICLE_LIGHT_EDGE_COLOR,
+            },
+            VehicleProfile::Heavy => VehicleColors {
+                body: VEHICLE_SECONDARY_BODY_COLOR,
+                edge: VEHICLE_HEAVY_EDGE_COLOR,
+            },
+            VehicleProfile::Predator => VehicleColors {
+                body: VEHICLE_PREDATOR_BODY_COLOR,
+                edge: VEHICLE_PREDATOR_EDGE_COLOR,
+            },
+        }
+    }
+
+    pub fn mass(&self) -> f32 {
+        match self {
+            VehicleProfile::Light => VEHICLE_MASS * 0.5,
+            VehicleProfile::Heavy => VEHICLE_MASS * 2.0,
+            VehicleProfile::Predator => VEHICLE_MASS * 0.8,
+            _ => VEHICLE_MASS,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SteeringForce {
+    Separation,
+    Alignment,
+    Cohesion,
+    View,
+    Target,
+    Flee,
+    Wander,
+    Bounds,
+    Pursue,
+    Evade,
+    Forage,
+    Follow,
+    Formation,
+}
+
+impl SteeringForce {
+    pub const ALL: [SteeringForce; 13] = [
+        SteeringForce::Separation,
+        SteeringForce::Alignment,
+        SteeringForce::Cohesion,
+        SteeringForce::View,
+        SteeringForce::Target,
+        SteeringForce::Flee,
+        SteeringForce::Wander,
+        SteeringForce::Bounds,
+        SteeringForce::Pursue,
+        SteeringForce::Evade,
+        SteeringForce::Forage,
+        SteeringForce::Follow,
+        SteeringForce::Formation,
+    ];
+}
+
+// What acted on the vehicle during the last steering pass
+#[derive(Component, Default)]
+pub struct Steering {
+    pub neighbours: usize,
+    pub dominant: Option<SteeringForce>,
+    strongest: f32,
+}
+
+impl Steering {
+    pub fn record(&mut self, kind: SteeringForce, force: Vec2) {
+        let magnitude = force.length_squared();
+        if magnitude > self.strongest {
+            self.strongest = magnitude;
+            self.dominant = Some(kind);
+        }
+    }
+}
+
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum VehicleSystem {
+    // Resets the steering state, other steering systems run after it
+    Flock,
+    Steer,
+    Move,
+}
+
+// Systems that despawn vehicles after the update stage, in the order they run
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DespawnSystem {
+    Erase,
+    Absorb,
+    Capture,
+    Starve,
+}
+
+// Vehicles despawned this frame. Despawns are only applied at the end of the stage, so
+// each despawning system skips the vehicles an earlier one already took
+#[derive(Resource, Default)]
+pub struct Despawned(HashSet<Entity>);
+
+impl Despawned {
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.0.contains(&entity)
+    }
+
+    // Despawns the vehicle unless it is already gone, returns whether it was taken
+    pub fn take(&mut self, commands: &mut Commands, entity: Entity) -> bool {
+        if !self.0.insert(entity) {
+            return false;
+        }
+
+        commands.entity(entity).despawn();
+        true
+    }
+}
 
 impl Acceleration {
-    fn apply_force(&mut self, force: Vec2, mass: &Mass) {
+    pub fn apply_force(&mut self, force: Vec2, mass: &Mass) {
         self.0 += force / mass.0;
     }
 }
 
+// Force turning `velocity` towards `desired`, limited like every steering force
+pub fn steer(desired: Vec2, velocity: Vec2) -> Vec2 {
+    (desired - velocity).clamp(-VEHICLE_MAX_FORCE, VEHICLE_MAX_FORCE)
+}
+
 impl Plugin for VehiclePlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(spawn_vehicles)
-            .add_system(vehicle_spawner)
-            .add_system(calc_movement)
-            .add_system(update);
+        app.snapshot_component::<Transform>()
+            .snapshot_component::<Name>()
+            .snapshot_component::<Vehicle>()
+            .snapshot_component::<Velocity>()
+            .snapshot_component::<Acceleration>()
+            .snapshot_component::<Mass>()
+            .snapshot_component::<WanderTheta>()
+            .snapshot_component::<VehicleColors>()
+            .snapshot_component::<Team>()
+            .snapshot_component::<VehicleProfile>()
+            .snapshot_component::<Genome>()
+            .add_event::<SpawnVehicle>()
+            .init_resource::<Despawned>()
+            .add_system_to_stage(CoreStage::First, clear_despawned)
+            .add_startup_system(spawn_vehicles)
+            .add_system(spawn_requested_vehicles)
+            .add_system(attach_vehicle_shapes)
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(simulating)
+                    .with_system(
+                        calc_movement
+                            .label(VehicleSystem::Flock)
+                            .label(VehicleSystem::Steer),
+                    )
+                    .with_system(
+                        field_forces
+                            .label(VehicleSystem::Steer)
+                            .after(VehicleSystem::Flock),
+                    )
+                    .with_system(
+                        update
+                            .label(VehicleSystem::Move)
+                            .after(VehicleSystem::Steer),
+                    ),
+            );
     }
 }
 
-fn spawn_vehicles(mut commands: Commands, windows: Res<Windows>) {
-    let mut rng = rand::thread_rng();
-    let window = windows.get_primary().unwrap();
-    let window_size = Vec2::new(window.width() as f32, window.height() as f32) / 2.0 - WALL_MARGIN;
+fn vehicle_geometry() -> GeometryBuilder {
+    let shape = shapes::RegularPolygon {
+        sides: 3,
+        feature: shapes::RegularPolygonFeature::Radius(VEHICLE_SIZE),
+        ..shapes::RegularPolygon::default()
+    };
 
-    for i in 0..=VEHICLE_COUNT {
-        let x = rng.gen_range(-window_size.x..window_size.x);
-        let y = rng.gen_range(-window_size.y..window_size.y);
+    let line = shapes::Line {
+        0: shape.center,
+        1: shape.center + Vec2::new(0.0, VEHICLE_SIZE),
+    };
 
-        let shape = shapes::RegularPolygon {
-            sides: 3,
-            feature: shapes::RegularPolygonFeature::Radius(VEHICLE_SIZE),
-            ..shapes::RegularPolygon::default()
-        };
-
-        let line = shapes::Line {
-            0: shape.center,
-            1: shape.center + Vec2::new(0.0, VEHICLE_SIZE),
-        };
-
-        let builder = GeometryBuilder::new().add(&shape).add(&line);
-
-        commands
-            .spawn(builder.build(
-                DrawMode::Outlined {
-                    fill_mode: {
-                        bevy_prototype_lyon::draw::FillMode {
-                            options: FillOptions::non_zero(),
-                            color: VEHICLE_BODY_COLOR,
-                        }
-                    },
-                    outline_mode: StrokeMode::new(VEHICLE_EDGE_COLOR, LINE_WIDTH),
-                },
+    GeometryBuilder::new().add(&shape).add(&line)
+}
+
+fn vehicle_draw_mode(colors: &VehicleColors) -> DrawMode {
+    DrawMode::Outlined {
+        fill_mode: {
+            bevy_prototype_lyon::draw::FillMode {
+                options: FillOptions::non_zero(),
+                color: colors.body,
+            }
+        },
+        outline_mode: StrokeMode::new(colors.edge, LINE_WIDTH),
+    }
+}
+
+fn heading(velocity: Vec2) -> Quat {
+    if velocity == Vec2::ZERO {
+        Quat::IDENTITY
+    } else {
+        Quat::from_rotation_z(velocity.y.atan2(velocity.x) - PI / 2.0)
+    }
+}
+
+// Everything a vehicle carries, however it was added
+#[derive(Bundle)]
+pub struct VehicleBundle {
+    #[bundle]
+    pub shape: ShapeBundle,
+    pub vehicle: Vehicle,
+    pub velocity: Velocity,
+    pub acceleration: Acceleration,
+    pub mass: Mass,
+    pub wander_theta: WanderTheta,
+    pub colors: VehicleColors,
+    pub steering: Steering,
+    pub team: Team,
+    pub profile: VehicleProfile,
+    pub genome: Genome,
+    pub energy: Energy,
+    pub trail: Trail,
+    pub tint: Tint,
+}
+
+impl VehicleBundle {
+    pub fn new(request: &SpawnVehicle) -> Self {
+        let colors = request.profile.colors();
+
+        Self {
+            shape: vehicle_geometry().build(
+                vehicle_draw_mode(&colors),
                 Transform {
-                    translation: Vec3::new(x, y, 900.0),
+                    translation: request.position.extend(VEHICLE_Z),
+                    rotation: heading(request.velocity),
                     ..Default::default()
                 },
-            ))
-            .insert(Vehicle)
-            .insert(Velocity(Vec2::ZERO))
-            .insert(Acceleration(Vec2::ZERO))
-            .insert(Mass(VEHICLE_MASS))
-            .insert(WanderTheta(0.0))
-            .insert(Name::new(format!("{}_{}", "Vehicle", i)));
+            ),
+            vehicle: Vehicle,
+            velocity: Velocity(request.velocity),
+            acceleration: Acceleration(Vec2::ZERO),
+            mass: Mass(request.profile.mass()),
+            wander_theta: WanderTheta(0.0),
+            colors,
+            steering: Steering::default(),
+            team: request.team,
+            profile: request.profile,
+            genome: request.genome,
+            energy: Energy(ENERGY_START),
+            trail: Trail::default(),
+            tint: Tint {
+                body: colors.body,
+                edge: colors.edge,
+            },
+        }
     }
 }
 
-fn vehicle_spawner(
-    mut commands: Commands,
-    windows: Res<Windows>,
-    kbd: Res<Input<KeyCode>>,
-    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
-) {
-    let window = windows.get_primary().unwrap();
-    let window_size = Vec2::new(window.width() as f32, window.height() as f32);
-    let (camera, camera_transform) = camera_query.single();
+// Sent by anything that wants a vehicle added, spawned by `spawn_requested_vehicles`
+#[derive(Clone, Copy)]
+pub struct SpawnVehicle {
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub profile: VehicleProfile,
+    pub team: Team,
+    pub genome: Genome,
+}
+
+// Spawns right away for callers that need the entity, like the replay
+pub fn spawn_vehicle(commands: &mut Commands, request: &SpawnVehicle) -> Entity {
+    let entity = commands.spawn(VehicleBundle::new(request)).id();
 
-    if let Some(_position) = window.cursor_position() {
-        if kbd.pressed(KeyCode::Space) {
-            let ndc = (_position / window_size) * 2.0 - Vec2::ONE;
-            let ndc_to_world =
-                camera_transform.compute_matrix() * camera.projection_matrix().inverse();
-            let world_pos = ndc_to_world.project_point3(ndc.extend(-1.0));
-            let world_pos: Vec2 = world_pos.truncate();
+    // Named here rather than in the bundle, the name needs the entity
+    commands
+        .entity(entity)
+        .insert(Name::new(format!("Vehicle_{}", entity.index())));
 
-            let shape = shapes::RegularPolygon {
-                sides: 3,
-                feature: shapes::RegularPolygonFeature::Radius(VEHICLE_SIZE),
-                ..shapes::RegularPolygon::default()
-            };
+    entity
+}
 
-            let line = shapes::Line {
-                0: shape.center,
-                1: shape.center + Vec2::new(0.0, VEHICLE_SIZE),
-            };
+fn clear_despawned(mut despawned: ResMut<Despawned>) {
+    despawned.0.clear();
+}
 
-            let builder = GeometryBuilder::new().add(&shape).add(&line);
+fn spawn_requested_vehicles(mut commands: Commands, mut requests: EventReader<SpawnVehicle>) {
+    for request in requests.iter() {
+        spawn_vehicle(&mut commands, request);
+    }
+}
 
+// Vehicles restored from a snapshot only carry their simulation state
+fn attach_vehicle_shapes(
+    mut commands: Commands,
+    vehicle_query: Query<
+        (
+            Entity,
+            &Transform,
+            Option<&VehicleColors>,
+            Option<&Team>,
+            Option<&VehicleProfile>,
+            Option<&Genome>,
+            Option<&Energy>,
+        ),
+        (With<Vehicle>, Without<Path>),
+    >,
+) {
+    vehicle_query.for_each(
+        |(entity, transform, colors, team, profile, genome, energy)| {
+            let colors = colors.copied().unwrap_or_default();
             commands
-                .spawn(builder.build(
-                    DrawMode::Outlined {
-                        fill_mode: {
-                            bevy_prototype_lyon::draw::FillMode {
-                                options: FillOptions::non_zero(),
-                                color: VEHICLE_SECONDARY_BODY_COLOR,
-                            }
-                        },
-                        outline_mode: StrokeMode::new(VEHICLE_SECONDARY_EDGE_COLOR, LINE_WIDTH),
-                    },
-                    Transform {
-                        translation: world_pos.extend(900.0),
-                        ..Default::default()
-                    },
-                ))
-                .insert(Vehicle)
-                .insert(Velocity(Vec2::ZERO))
-                .insert(Acceleration(Vec2::ZERO))
-                .insert(Mass(VEHICLE_MASS))
-                .insert(WanderTheta(0.0));
-        }
+                .entity(entity)
+                .insert(vehicle_geometry().build(vehicle_draw_mode(&colors), *transform))
+                .insert(colors)
+                .insert(team.copied().unwrap_or_default())
+                .insert(profile.copied().unwrap_or_default())
+                .insert(genome.copied().unwrap_or_default())
+                .insert(Energy(energy.map_or(ENERGY_START, |energy| energy.0)))
+                .insert(Trail::default())
+                .insert(Tint {
+                    body: colors.body,
+                    edge: colors.edge,
+                })
+                .insert(Steering::default());
+        },
+    );
+}
+
+fn spawn_vehicles(mut commands: Commands, windows: Res<Windows>, mut rng: ResMut<SimRng>) {
+    let window = windows.get_primary().unwrap();
+    let window_size = Vec2::new(window.width() as f32, window.height() as f32) / 2.0 - WALL_MARGIN;
+
+    for _ in 0..=VEHICLE_COUNT {
+        let x = rng.gen_range(-window_size.x..window_size.x);
+        let y = rng.gen_range(-window_size.y..window_size.y);
+
+        spawn_vehicle(
+            &mut commands,
+            &SpawnVehicle {
+                position: Vec2::new(x, y),
+                velocity: Vec2::ZERO,
+                profile: VehicleProfile::Primary,
+                team: Team::default(),
+                genome: Genome::default(),
+            },
+        );
     }
 }
 
@@ -194,13 +539,33 @@ fn seek_steer(world_pos: &Vec2, transform: &Transform, desired: &mut Vec2) {
     }
 }
 
+// Team rules decide who flocks together, but predators and their prey never do
+fn flockmates(
+    rules: &TeamRules,
+    team: Team,
+    profile: VehicleProfile,
+    other_team: Team,
+    other_profile: VehicleProfile,
+) -> bool {
+    rules.rule(team, other_team) == TeamRule::Flock
+        && (profile == VehicleProfile::Predator) == (other_profile == VehicleProfile::Predator)
+}
+
 fn flock(
     acceleration: &mut Acceleration,
+    steering: &mut Steering,
     transform: &Transform,
     velocity: &Velocity,
     mass: &Mass,
-    other_vehicle_query: &Query<(&Transform, &Velocity), With<Vehicle>>,
+    genome: &Genome,
+    flocks_with: impl Fn(Team, VehicleProfile) -> bool,
+    other_vehicle_query: &Query<(&Transform, &Velocity, &Team, &VehicleProfile), With<Vehicle>>,
 ) {
+    let seperation_dist_sq = genome.separation_dist * genome.separation_dist;
+    let align_dist_sq = genome.align_dist * genome.align_dist;
+    let cohesion_dist_sq = genome.cohesion_dist * genome.cohesion_dist;
+    let view_dist_sq = genome.view_dist * genome.view_dist;
+
     // Seperate
     let mut seperate_sum = Vec2::new(0.0, 0.0);
     let mut seperate_count = 0;
@@ -217,70 +582,76 @@ fn flock(
     let mut view_sum = Vec2::new(0.0, 0.0);
     let mut view_count = 0;
 
-    other_vehicle_query.for_each(|(other_transform, other_velocity)| {
-        let dist = transform
-            .translation
-            .truncate()
-            .distance_squared(other_transform.translation.truncate());
-
-        // Seperate
-        if dist > 0.0 {
-            if dist <= VEHICLE_SEPERATION_DIST_SQ {
-                seperate_sum += (transform.translation.truncate()
-                    - other_transform.translation.truncate())
-                .normalize_or_zero()
-                    / dist.sqrt();
-
-                seperate_count += 1;
+    other_vehicle_query.for_each(
+        |(other_transform, other_velocity, other_team, other_profile)| {
+            if !flocks_with(*other_team, *other_profile) {
+                return;
             }
 
-            // Align
-            if dist <= VEHICLE_ALIGN_DIST_SQ {
-                align_sum += other_velocity.0;
-                align_count += 1;
-            }
+            let dist = transform
+                .translation
+                .truncate()
+                .distance_squared(other_transform.translation.truncate());
 
-            // Cohesion
-            if dist <= VEHICLE_COHESION_DIST_SQ {
-                cohesion_sum += other_transform.translation.truncate();
-                cohesion_count += 1;
-            }
+            // Seperate
+            if dist > 0.0 {
+                if dist <= seperation_dist_sq {
+                    seperate_sum += (transform.translation.truncate()
+                        - other_transform.translation.truncate())
+                    .normalize_or_zero()
+                        / dist.sqrt();
 
-            // View
-            if dist <= VEHICLE_VIEW_DIST_SQ && view_count == 0 {
-                let path =
-                    other_transform.translation.truncate() - transform.translation.truncate();
+                    seperate_count += 1;
+                }
 
-                let angle = velocity.0.angle_between(path);
+                // Align
+                if dist <= align_dist_sq {
+                    align_sum += other_velocity.0;
+                    align_count += 1;
+                }
 
-                if angle.abs() < VEHICLE_VIEW_ANGLE {
-                    let val = path.perp();
+                // Cohesion
+                if dist <= cohesion_dist_sq {
+                    cohesion_sum += other_transform.translation.truncate();
+                    cohesion_count += 1;
+                }
 
-                    let angle_1 = velocity.0.angle_between(val);
-                    let angle_2 = velocity.0.angle_between(-val);
+                // View
+                if dist <= view_dist_sq && view_count == 0 {
+                    let path =
+                        other_transform.translation.truncate() - transform.translation.truncate();
 
-                    if angle_1.abs() < angle_2.abs() {
-                        view_sum += val.normalize_or_zero();
-                    } else {
-                        view_sum -= val.normalize_or_zero();
-                    }
+                    let angle = velocity.0.angle_between(path);
+
+                    if angle.abs() < VEHICLE_VIEW_ANGLE {
+                        let val = path.perp();
+
+                        let angle_1 = velocity.0.angle_between(val);
+                        let angle_2 = velocity.0.angle_between(-val);
 
-                    view_count += 1;
+                        if angle_1.abs() < angle_2.abs() {
+                            view_sum += val.normalize_or_zero();
+                        } else {
+                            view_sum -= val.normalize_or_zero();
+                        }
+
+                        view_count += 1;
+                    }
                 }
             }
-        }
-    });
+        },
+    );
+
+    steering.neighbours = align_count;
 
     // Seperate
     if seperate_count > 0 {
         seperate_sum /= seperate_count as f32;
         seperate_sum = seperate_sum.normalize_or_zero() * VEHICLE_MAX_SPEED;
 
-        acceleration.apply_force(
-            (seperate_sum - velocity.0).clamp(-VEHICLE_MAX_FORCE, VEHICLE_MAX_FORCE)
-                * VEHICLE_SEPERATION_FACTOR,
-            mass,
-        );
+        let force = steer(seperate_sum, velocity.0) * genome.separation;
+        steering.record(SteeringForce::Separation, force);
+        acceleration.apply_force(force, mass);
     }
 
     // Align
@@ -288,11 +659,9 @@ fn flock(
         align_sum /= align_count as f32;
         align_sum = align_sum.normalize_or_zero() * VEHICLE_MAX_SPEED;
 
-        acceleration.apply_force(
-            (align_sum - velocity.0).clamp(-VEHICLE_MAX_FORCE, VEHICLE_MAX_FORCE)
-                * VEHICLE_ALIGN_FACTOR,
-            mass,
-        );
+        let force = steer(align_sum, velocity.0) * genome.alignment;
+        steering.record(SteeringForce::Alignment, force);
+        acceleration.apply_force(force, mass);
     }
 
     // Cohesion
@@ -302,11 +671,9 @@ fn flock(
 
         seek_steer(&cohesion_sum, &transform, &mut desired);
 
-        acceleration.apply_force(
-            (desired - velocity.0).clamp(-VEHICLE_MAX_FORCE, VEHICLE_MAX_FORCE)
-                * VEHICLE_COHESION_FACTOR,
-            mass,
-        );
+        let force = steer(desired, velocity.0) * genome.cohesion;
+        steering.record(SteeringForce::Cohesion, force);
+        acceleration.apply_force(force, mass);
     }
 
     // View
@@ -314,11 +681,9 @@ fn flock(
         view_sum /= view_count as f32;
         view_sum = view_sum.normalize_or_zero() * VEHICLE_MAX_SPEED;
 
-        acceleration.apply_force(
-            (view_sum - velocity.0).clamp(-VEHICLE_MAX_FORCE, VEHICLE_MAX_FORCE)
-                * VEHICLE_VIEW_FACTOR,
-            mass,
-        );
+        let force = steer(view_sum, velocity.0) * genome.view;
+        steering.record(SteeringForce::View, force);
+        acceleration.apply_force(force, mass);
     }
 }
 
@@ -330,46 +695,66 @@ fn calc_movement(
             &mut Acceleration,
             &Mass,
             &mut WanderTheta,
+            &mut Steering,
+            &Genome,
+            &Team,
+            &VehicleProfile,
         ),
-        With<Vehicle>,
+        // Formations steer their own members
+        (With<Vehicle>, Without<FormationMember>),
     >,
-    other_vehicle_query: Query<(&Transform, &Velocity), With<Vehicle>>,
-    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    other_vehicle_query: Query<(&Transform, &Velocity, &Team, &VehicleProfile), With<Vehicle>>,
+    rules: Res<TeamRules>,
+    target_query: Query<(&Transform, &Target), Without<Vehicle>>,
     windows: Res<Windows>,
-    buttons: Res<Input<MouseButton>>,
+    mut rng: ResMut<SimRng>,
+    clock: Res<SimClock>,
 ) {
     let window = windows.get_primary().unwrap();
-    let (camera, camera_transform) = camera_query.single();
     let window_size = Vec2::new(window.width() as f32, window.height() as f32);
 
     let mut wander = true;
 
-    if let Some(_position) = window.cursor_position() {
-        let ndc = (_position / window_size) * 2.0 - Vec2::ONE;
-        let ndc_to_world = camera_transform.compute_matrix() * camera.projection_matrix().inverse();
-        let world_pos = ndc_to_world.project_point3(ndc.extend(-1.0));
-        let world_pos: Vec2 = world_pos.truncate();
-
-        // Folow mouse position
-        if buttons.pressed(MouseButton::Left) {
-            vehicle_query.for_each_mut(|(velocity, transform, mut acceleration, mass, _)| {
-                let mut desired = Vec2::ZERO;
-                seek_steer(&world_pos, &transform, &mut desired);
-
-                acceleration.apply_force(
-                    (desired - velocity.0).clamp(-VEHICLE_MAX_FORCE, VEHICLE_MAX_FORCE)
-                        * VEHICLE_TARGET_FACTOR,
-                    mass,
-                );
+    if let Ok((target_transform, target)) = target_query.get_single() {
+        let world_pos = target_transform.translation.truncate();
 
-                flock(
-                    &mut acceleration,
-                    &transform,
-                    &velocity,
+        // Folow the target
+        if target.engaged {
+            vehicle_query.for_each_mut(
+                |(
+                    velocity,
+                    transform,
+                    mut acceleration,
                     mass,
-                    &other_vehicle_query,
-                );
-            });
+                    _,
+                    mut steering,
+                    genome,
+                    team,
+                    profile,
+                )| {
+                    *steering = Steering::default();
+
+                    let mut desired = Vec2::ZERO;
+                    seek_steer(&world_pos, &transform, &mut desired);
+
+                    let force = steer(desired, velocity.0) * VEHICLE_TARGET_FACTOR;
+                    steering.record(SteeringForce::Target, force);
+                    acceleration.apply_force(force, mass);
+
+                    flock(
+                        &mut acceleration,
+                        &mut steering,
+                        &transform,
+                        &velocity,
+                        mass,
+                        genome,
+                        |other_team, other_profile| {
+                            flockmates(&rules, *team, *profile, other_team, other_profile)
+                        },
+                        &other_vehicle_query,
+                    );
+                },
+            );
 
             wander = false;
         }
@@ -379,19 +764,36 @@ fn calc_movement(
 
     // Wander
     if wander {
-        let mut rng = rand::thread_rng();
-        let range = PI / 8.0;
+        // The heading drifts once per frame, so by as much more as the clock runs faster
+        let range = PI / 8.0 * clock.scale();
 
         vehicle_query.for_each_mut(
-            |(velocity, transform, mut acceleration, mass, mut wander_theta)| {
+            |(
+                velocity,
+                transform,
+                mut acceleration,
+                mass,
+                mut wander_theta,
+                mut steering,
+                genome,
+                team,
+                profile,
+            )| {
+                *steering = Steering::default();
+
                 let fx = transform.translation.x < -bounds.x || transform.translation.x > bounds.x;
                 let fy = transform.translation.y < -bounds.y || transform.translation.y > bounds.y;
 
                 flock(
                     &mut acceleration,
+                    &mut steering,
                     &transform,
                     &velocity,
                     mass,
+                    genome,
+                    |other_team, other_profile| {
+                        flockmates(&rules, *team, *profile, other_team, other_profile)
+                    },
                     &other_vehicle_query,
                 );
 
@@ -408,11 +810,9 @@ fn calc_movement(
                     let desired = (target - transform.translation.truncate()).normalize_or_zero()
                         * VEHICLE_WANDER_SPEED;
 
-                    acceleration.apply_force(
-                        (desired - velocity.0).clamp(-VEHICLE_MAX_FORCE, VEHICLE_MAX_FORCE)
-                            * VEHICLE_WANDER_FACTOR,
-                        mass,
-                    );
+                    let force = steer(desired, velocity.0) * genome.wander;
+                    steering.record(SteeringForce::Wander, force);
+                    acceleration.apply_force(force, mass);
                     return;
                 }
 
@@ -437,26 +837,78 @@ fn calc_movement(
                     },
                 );
 
-                acceleration.apply_force(
-                    (desired - velocity.0).clamp(-VEHICLE_MAX_FORCE, VEHICLE_MAX_FORCE)
-                        * VEHICLE_LIMIT_FACTOR,
-                    mass,
-                );
+                let force = steer(desired, velocity.0) * VEHICLE_LIMIT_FACTOR;
+                steering.record(SteeringForce::Bounds, force);
+                acceleration.apply_force(force, mass);
             },
         );
     }
 }
 
+// Placed attractors and repulsors act on top of whatever else steers the vehicle
+fn field_forces(
+    mut vehicle_query: Query<
+        (
+            &Velocity,
+            &Transform,
+            &mut Acceleration,
+            &Mass,
+            &mut Steering,
+        ),
+        (With<Vehicle>, Without<FormationMember>),
+    >,
+    field_query: Query<(&Transform, &TargetField)>,
+) {
+    if field_query.is_empty() {
+        return;
+    }
+
+    vehicle_query.for_each_mut(
+        |(velocity, transform, mut acceleration, mass, mut steering)| {
+            let position = transform.translation.truncate();
+
+            for (field_transform, field) in field_query.iter() {
+                let offset = field_transform.translation.truncate() - position;
+                let dist = offset.length();
+
+                if dist > field.radius {
+                    continue;
+                }
+
+                let (kind, desired, falloff) = match field.kind {
+                    // Arrive, slowing down towards the center
+                    TargetKind::Attract => (
+                        SteeringForce::Target,
+                        offset.normalize_or_zero() * VEHICLE_MAX_SPEED * dist / field.radius,
+                        1.0,
+                    ),
+                    // Flee, pushing harder the closer the vehicle is
+                    TargetKind::Repel => (
+                        SteeringForce::Flee,
+                        -offset.normalize_or_zero() * VEHICLE_MAX_SPEED,
+                        1.0 - dist / field.radius,
+                    ),
+                };
+
+                let force = steer(desired, velocity.0) * field.strength * falloff;
+                steering.record(kind, force);
+                acceleration.apply_force(force, mass);
+            }
+        },
+    );
+}
+
 fn update(
     mut vehicle_query: Query<(&mut Velocity, &mut Acceleration, &mut Transform), With<Vehicle>>,
-    time: Res<Time>,
+    clock: Res<SimClock>,
 ) {
     vehicle_query.for_each_mut(|(mut velocity, mut acceleration, mut transform)| {
-        velocity.0 =
-            (velocity.0 + acceleration.0).clamp(-VEHICLE_MAX_SPEED_VEC, VEHICLE_MAX_SPEED_VEC);
+        // Steering forces are applied once per frame, so they scale with the clock as well
+        velocity.0 = (velocity.0 + acceleration.0 * clock.scale())
+            .clamp(-VEHICLE_MAX_SPEED_VEC, VEHICLE_MAX_SPEED_VEC);
 
-        transform.translation.x += velocity.x * time.delta_seconds();
-        transform.translation.y += velocity.y * time.delta_seconds();
+        transform.translation.x += velocity.x * clock.delta_seconds();
+        transform.translation.y += velocity.y * clock.delta_seconds();
 
         transform.rotation = Quat::from_rotation_z(velocity.y.atan2(velocity.x) - PI / 2.0);
 